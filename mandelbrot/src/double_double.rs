@@ -0,0 +1,622 @@
+use num::traits::{Float, Num, NumCast, One, ToPrimitive, Zero};
+use std::cmp::Ordering;
+use std::fmt;
+use std::num::FpCategory;
+use std::ops::{Add, Div, Mul, Neg, Rem, Sub};
+use std::str::FromStr;
+
+/// A floating-point number represented as the unevaluated sum of two `f64`s.
+///
+/// `hi` holds the value rounded to the nearest `f64`, and `lo` holds the
+/// rounding error, so a `DoubleDouble` carries about 106 bits of mantissa,
+/// roughly 31 decimal digits. That is enough to zoom about 15 digits deeper
+/// than plain `f64` before the pixels of an image collapse onto each other.
+///
+/// The arithmetic operators, `sqrt` and parsing keep the full precision.
+/// Transcendental functions like `sin` or `ln` are only computed to `f64`
+/// precision: the renderer never uses them, and `num::Float` requires them.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+impl DoubleDouble {
+    pub fn new(hi: f64, lo: f64) -> DoubleDouble {
+        let (hi, lo) = two_sum(hi, lo);
+        DoubleDouble { hi, lo }
+    }
+
+    fn from_f64(x: f64) -> DoubleDouble {
+        DoubleDouble { hi: x, lo: 0.0 }
+    }
+}
+
+/// Return `a + b` rounded to an `f64`, along with the rounding error.
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    if !s.is_finite() {
+        return (s, 0.0);
+    }
+    let bb = s - a;
+    (s, (a - (s - bb)) + (b - bb))
+}
+
+/// Like `two_sum`, but only correct when `|a| >= |b|`.
+fn quick_two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    if !s.is_finite() {
+        return (s, 0.0);
+    }
+    (s, b - (s - a))
+}
+
+/// Return `a * b` rounded to an `f64`, along with the rounding error.
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    if !p.is_finite() {
+        return (p, 0.0);
+    }
+    (p, a.mul_add(b, -p))
+}
+
+impl Add for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn add(self, rhs: DoubleDouble) -> DoubleDouble {
+        let (s, e) = two_sum(self.hi, rhs.hi);
+        let (t, f) = two_sum(self.lo, rhs.lo);
+        let (s, e) = quick_two_sum(s, e + t);
+        let (hi, lo) = quick_two_sum(s, e + f);
+        DoubleDouble { hi, lo }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn sub(self, rhs: DoubleDouble) -> DoubleDouble {
+        self + -rhs
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn mul(self, rhs: DoubleDouble) -> DoubleDouble {
+        let (p, e) = two_prod(self.hi, rhs.hi);
+        // The cross terms of an overflowing product would turn it into NaN.
+        if !p.is_finite() {
+            return DoubleDouble::from_f64(p);
+        }
+        let (hi, lo) = quick_two_sum(p, e + (self.hi * rhs.lo + self.lo * rhs.hi));
+        DoubleDouble { hi, lo }
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn div(self, rhs: DoubleDouble) -> DoubleDouble {
+        // Long division: each step corrects the quotient by the remainder
+        // left over from the previous one.
+        let q1 = self.hi / rhs.hi;
+        if !q1.is_finite() {
+            return DoubleDouble::from_f64(q1);
+        }
+        let r = self - rhs * DoubleDouble::from_f64(q1);
+        let q2 = r.hi / rhs.hi;
+        let r = r - rhs * DoubleDouble::from_f64(q2);
+        let q3 = r.hi / rhs.hi;
+
+        let (hi, lo) = quick_two_sum(q1, q2);
+        DoubleDouble { hi, lo } + DoubleDouble::from_f64(q3)
+    }
+}
+
+impl Rem for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn rem(self, rhs: DoubleDouble) -> DoubleDouble {
+        self - rhs * (self / rhs).trunc()
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = DoubleDouble;
+
+    fn neg(self) -> DoubleDouble {
+        DoubleDouble {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl PartialOrd for DoubleDouble {
+    fn partial_cmp(&self, other: &DoubleDouble) -> Option<Ordering> {
+        match self.hi.partial_cmp(&other.hi) {
+            Some(Ordering::Equal) => self.lo.partial_cmp(&other.lo),
+            ordering => ordering,
+        }
+    }
+}
+
+impl Zero for DoubleDouble {
+    fn zero() -> DoubleDouble {
+        DoubleDouble::from_f64(0.0)
+    }
+
+    fn is_zero(&self) -> bool {
+        self.hi == 0.0
+    }
+}
+
+impl One for DoubleDouble {
+    fn one() -> DoubleDouble {
+        DoubleDouble::from_f64(1.0)
+    }
+}
+
+impl Num for DoubleDouble {
+    type FromStrRadixErr = ParseDoubleDoubleError;
+
+    fn from_str_radix(s: &str, radix: u32) -> Result<DoubleDouble, ParseDoubleDoubleError> {
+        if radix == 10 {
+            s.parse()
+        } else {
+            f64::from_str_radix(s, radix)
+                .map(DoubleDouble::from_f64)
+                .map_err(|_| ParseDoubleDoubleError)
+        }
+    }
+}
+
+impl ToPrimitive for DoubleDouble {
+    fn to_i64(&self) -> Option<i64> {
+        let t = self.trunc();
+        t.hi.to_i64()?.checked_add(t.lo.to_i64()?)
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        self.to_i64().and_then(|n| n.to_u64())
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        Some(self.hi + self.lo)
+    }
+}
+
+impl NumCast for DoubleDouble {
+    fn from<T: ToPrimitive>(n: T) -> Option<DoubleDouble> {
+        n.to_f64().map(DoubleDouble::from_f64)
+    }
+}
+
+/// Implement `Float` methods by rounding to `f64` and calling the `f64`
+/// method of the same name.
+macro_rules! via_f64 {
+    ($($name:ident),*) => {
+        $(
+            fn $name(self) -> DoubleDouble {
+                DoubleDouble::from_f64(self.hi.$name())
+            }
+        )*
+    };
+}
+
+impl Float for DoubleDouble {
+    fn nan() -> DoubleDouble {
+        DoubleDouble::from_f64(f64::NAN)
+    }
+
+    fn infinity() -> DoubleDouble {
+        DoubleDouble::from_f64(f64::INFINITY)
+    }
+
+    fn neg_infinity() -> DoubleDouble {
+        DoubleDouble::from_f64(f64::NEG_INFINITY)
+    }
+
+    fn neg_zero() -> DoubleDouble {
+        DoubleDouble::from_f64(-0.0)
+    }
+
+    fn min_value() -> DoubleDouble {
+        DoubleDouble::from_f64(f64::MIN)
+    }
+
+    fn min_positive_value() -> DoubleDouble {
+        DoubleDouble::from_f64(f64::MIN_POSITIVE)
+    }
+
+    fn epsilon() -> DoubleDouble {
+        // 2^-104: the gap between 1.0 and the next representable value.
+        DoubleDouble::from_f64(f64::EPSILON * f64::EPSILON / 4.0)
+    }
+
+    fn max_value() -> DoubleDouble {
+        DoubleDouble::from_f64(f64::MAX)
+    }
+
+    fn is_nan(self) -> bool {
+        self.hi.is_nan()
+    }
+
+    fn is_infinite(self) -> bool {
+        self.hi.is_infinite()
+    }
+
+    fn is_finite(self) -> bool {
+        self.hi.is_finite()
+    }
+
+    fn is_normal(self) -> bool {
+        self.hi.is_normal()
+    }
+
+    fn classify(self) -> FpCategory {
+        self.hi.classify()
+    }
+
+    fn floor(self) -> DoubleDouble {
+        let hi = self.hi.floor();
+        if hi == self.hi {
+            DoubleDouble::new(hi, self.lo.floor())
+        } else {
+            DoubleDouble::from_f64(hi)
+        }
+    }
+
+    fn ceil(self) -> DoubleDouble {
+        let hi = self.hi.ceil();
+        if hi == self.hi {
+            DoubleDouble::new(hi, self.lo.ceil())
+        } else {
+            DoubleDouble::from_f64(hi)
+        }
+    }
+
+    fn round(self) -> DoubleDouble {
+        let half = DoubleDouble::from_f64(0.5);
+        if self.is_sign_negative() {
+            -(-self + half).floor()
+        } else {
+            (self + half).floor()
+        }
+    }
+
+    fn trunc(self) -> DoubleDouble {
+        if self.is_sign_negative() {
+            self.ceil()
+        } else {
+            self.floor()
+        }
+    }
+
+    fn fract(self) -> DoubleDouble {
+        self - self.trunc()
+    }
+
+    fn abs(self) -> DoubleDouble {
+        if self.is_sign_negative() {
+            -self
+        } else {
+            self
+        }
+    }
+
+    fn signum(self) -> DoubleDouble {
+        DoubleDouble::from_f64(self.hi.signum())
+    }
+
+    fn is_sign_positive(self) -> bool {
+        self.hi.is_sign_positive()
+    }
+
+    fn is_sign_negative(self) -> bool {
+        self.hi.is_sign_negative()
+    }
+
+    fn mul_add(self, a: DoubleDouble, b: DoubleDouble) -> DoubleDouble {
+        self * a + b
+    }
+
+    fn recip(self) -> DoubleDouble {
+        DoubleDouble::one() / self
+    }
+
+    fn powi(self, n: i32) -> DoubleDouble {
+        let mut base = self;
+        let mut exponent = n.unsigned_abs();
+        let mut result = DoubleDouble::one();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result * base;
+            }
+            base = base * base;
+            exponent >>= 1;
+        }
+        if n < 0 {
+            result.recip()
+        } else {
+            result
+        }
+    }
+
+    fn powf(self, n: DoubleDouble) -> DoubleDouble {
+        DoubleDouble::from_f64(self.hi.powf(n.hi))
+    }
+
+    fn sqrt(self) -> DoubleDouble {
+        if self.hi <= 0.0 || !self.hi.is_finite() {
+            return DoubleDouble::from_f64(self.hi.sqrt());
+        }
+        // One Newton step from the f64 square root doubles its precision.
+        let y = DoubleDouble::from_f64(self.hi.sqrt());
+        y + (self - y * y) / (y + y)
+    }
+
+    via_f64!(exp, exp2, ln, log2, log10, cbrt, sin, cos, tan, asin, acos, atan);
+    via_f64!(exp_m1, ln_1p, sinh, cosh, tanh, asinh, acosh, atanh);
+
+    fn log(self, base: DoubleDouble) -> DoubleDouble {
+        DoubleDouble::from_f64(self.hi.log(base.hi))
+    }
+
+    fn max(self, other: DoubleDouble) -> DoubleDouble {
+        if self.is_nan() || self < other {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn min(self, other: DoubleDouble) -> DoubleDouble {
+        if self.is_nan() || self > other {
+            other
+        } else {
+            self
+        }
+    }
+
+    fn abs_sub(self, other: DoubleDouble) -> DoubleDouble {
+        if self <= other {
+            DoubleDouble::zero()
+        } else {
+            self - other
+        }
+    }
+
+    fn hypot(self, other: DoubleDouble) -> DoubleDouble {
+        (self * self + other * other).sqrt()
+    }
+
+    fn atan2(self, other: DoubleDouble) -> DoubleDouble {
+        DoubleDouble::from_f64(self.hi.atan2(other.hi))
+    }
+
+    fn sin_cos(self) -> (DoubleDouble, DoubleDouble) {
+        (self.sin(), self.cos())
+    }
+
+    fn integer_decode(self) -> (u64, i16, i8) {
+        self.hi.integer_decode()
+    }
+}
+
+/// The error returned when a string can't be parsed as a `DoubleDouble`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParseDoubleDoubleError;
+
+impl fmt::Display for ParseDoubleDoubleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid double-double literal")
+    }
+}
+
+/// Parse a decimal literal like `-1.25`, `.5` or `6.02e23`.
+///
+/// Unlike going through `f64::from_str`, every digit is accumulated in
+/// double-double arithmetic, so literals with more than 17 significant digits
+/// keep their precision.
+impl FromStr for DoubleDouble {
+    type Err = ParseDoubleDoubleError;
+
+    fn from_str(s: &str) -> Result<DoubleDouble, ParseDoubleDoubleError> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let (mantissa, exponent) = match s.find(['e', 'E']) {
+            Some(index) => (
+                &s[..index],
+                i32::from_str(&s[index + 1..]).map_err(|_| ParseDoubleDoubleError)?,
+            ),
+            None => (s, 0),
+        };
+        let (integer, fraction) = match mantissa.find('.') {
+            Some(index) => (&mantissa[..index], &mantissa[index + 1..]),
+            None => (mantissa, ""),
+        };
+        if integer.is_empty() && fraction.is_empty() {
+            return Err(ParseDoubleDoubleError);
+        }
+
+        let ten = DoubleDouble::from_f64(10.0);
+        let mut value = DoubleDouble::zero();
+        for c in integer.chars().chain(fraction.chars()) {
+            let digit = c.to_digit(10).ok_or(ParseDoubleDoubleError)?;
+            value = value * ten + DoubleDouble::from_f64(digit as f64);
+        }
+        // Zero stays zero, however large its exponent.
+        if value == DoubleDouble::zero() {
+            return Ok(if negative { -value } else { value });
+        }
+
+        // Exponents this far out would overflow, long before they could
+        // describe a representable number.
+        let exponent = i32::try_from(fraction.len())
+            .ok()
+            .and_then(|len| exponent.checked_sub(len))
+            .ok_or(ParseDoubleDoubleError)?;
+        if exponent < 0 {
+            let exponent = exponent.checked_neg().ok_or(ParseDoubleDoubleError)?;
+            // Dividing by a power of ten too big for an f64 would give NaN,
+            // where the number is simply too small to represent.
+            let divisor = ten.powi(exponent);
+            value = if !divisor.is_finite() {
+                DoubleDouble::zero()
+            } else {
+                value / divisor
+            };
+        } else {
+            value = value * ten.powi(exponent);
+        }
+
+        Ok(if negative { -value } else { value })
+    }
+}
+
+/// Format in scientific notation with up to 31 significant digits, or with
+/// as many digits after the point as the formatter's precision asks for.
+impl fmt::Display for DoubleDouble {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.hi.is_finite() || self.hi == 0.0 {
+            return write!(f, "{}", self.hi);
+        }
+
+        let ten = DoubleDouble::from_f64(10.0);
+        let mut exponent = self.hi.abs().log10().floor() as i32;
+        let mut x = self.abs() / ten.powi(exponent);
+        // The f64 logarithm can be off by one near powers of ten.
+        if x.hi >= 10.0 {
+            x = x / ten;
+            exponent += 1;
+        } else if x.hi < 1.0 {
+            x = x * ten;
+            exponent -= 1;
+        }
+
+        // Round by adding half a unit in the last place shown, so that the
+        // digits can then simply be truncated.
+        let digits = f.precision().map_or(31, |p| p + 1);
+        x = x + DoubleDouble::from_f64(0.5) / ten.powi(digits as i32 - 1);
+        if x.hi >= 10.0 {
+            x = x / ten;
+            exponent += 1;
+        }
+
+        let mut mantissa = String::new();
+        for i in 0..digits {
+            let digit = x.hi.floor().clamp(0.0, 9.0);
+            mantissa.push(char::from(b'0' + digit as u8));
+            if i == 0 && digits > 1 {
+                mantissa.push('.');
+            }
+            x = (x - DoubleDouble::from_f64(digit)) * ten;
+        }
+        if f.precision().is_none() {
+            mantissa = mantissa
+                .trim_end_matches('0')
+                .trim_end_matches('.')
+                .to_string();
+        }
+
+        let sign = if self.is_sign_negative() { "-" } else { "" };
+        write!(f, "{}{}e{}", sign, mantissa, exponent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dd(s: &str) -> DoubleDouble {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic_keeps_low_bits() {
+        let one = DoubleDouble::one();
+        let tiny = DoubleDouble::from_f64(1e-20);
+        assert_eq!((one + tiny) - one, tiny);
+        assert_eq!((one + tiny) * (one + tiny), one + tiny + tiny);
+
+        let third = one / DoubleDouble::from_f64(3.0);
+        let error = (third * DoubleDouble::from_f64(3.0) - one).abs();
+        assert!(error < DoubleDouble::from_f64(1e-31));
+    }
+
+    #[test]
+    fn test_sqrt() {
+        let two = DoubleDouble::from_f64(2.0);
+        let root = two.sqrt();
+        assert!((root * root - two).abs() < DoubleDouble::from_f64(1e-31));
+        assert_eq!(
+            DoubleDouble::from_f64(16.0).sqrt(),
+            DoubleDouble::from_f64(4.0)
+        );
+    }
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(dd("2.5").floor(), dd("2"));
+        assert_eq!(dd("-2.5").floor(), dd("-3"));
+        assert_eq!(dd("-2.5").trunc(), dd("-2"));
+        assert_eq!(dd("2.5").round(), dd("3"));
+        assert_eq!(dd("1.000000000000000000000001").floor(), dd("1"));
+        assert_eq!(dd("1.000000000000000000000001").ceil(), dd("2"));
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            dd("0.1") * DoubleDouble::from_f64(10.0),
+            DoubleDouble::one()
+        );
+        assert_eq!(dd("-1.25"), DoubleDouble::from_f64(-1.25));
+        assert_eq!(dd("6.25e2"), DoubleDouble::from_f64(625.0));
+        assert_eq!(dd(".5"), DoubleDouble::from_f64(0.5));
+
+        // These differ past the 17th digit, which f64 can't represent.
+        assert!(dd("0.100000000000000000001") > dd("0.1"));
+
+        assert_eq!("".parse::<DoubleDouble>(), Err(ParseDoubleDoubleError));
+        assert_eq!("1.2.3".parse::<DoubleDouble>(), Err(ParseDoubleDoubleError));
+        assert_eq!("1e".parse::<DoubleDouble>(), Err(ParseDoubleDoubleError));
+
+        // Exponents that overflow are errors, not panics.
+        for s in ["1e-2147483648", "0.5e-2147483648", "0.5e-2147483647"] {
+            assert_eq!(
+                s.parse::<DoubleDouble>(),
+                Err(ParseDoubleDoubleError),
+                "{}",
+                s
+            );
+        }
+        assert_eq!(dd("1e-2147483647"), DoubleDouble::zero());
+        assert_eq!(dd("1e-400"), DoubleDouble::zero());
+
+        // Numbers too big for an f64 overflow to infinity, as with f64.
+        assert_eq!(dd("1e400"), DoubleDouble::infinity());
+        assert_eq!(dd("-1e400"), -DoubleDouble::infinity());
+        assert_eq!(dd("0e400"), DoubleDouble::zero());
+        assert_eq!(
+            DoubleDouble::infinity() * DoubleDouble::from_f64(2.0),
+            DoubleDouble::infinity()
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(dd("-1.25").to_string(), "-1.25e0");
+        assert_eq!(dd("0.1").to_string(), "1e-1");
+        assert_eq!(
+            dd("1.2345678901234567890123456789").to_string(),
+            "1.2345678901234567890123456789e0"
+        );
+        assert_eq!(format!("{:.3}", dd("1234.5678")), "1.235e3");
+        assert_eq!(format!("{:.0}", dd("1234")), "1e3");
+    }
+}
@@ -1,16 +1,65 @@
+use double_double::DoubleDouble;
 use image::codecs::png::{self, PngEncoder};
-use image::{ColorType, GrayImage, ImageEncoder};
-use num::Complex;
+use image::{ColorType, GrayImage, ImageEncoder, ImageResult};
+use num::{Complex, Float};
 use std::env;
 use std::f64::INFINITY;
 use std::fs::File;
+use std::io::Write;
 use std::str::FromStr;
 
+mod double_double;
+mod nucleus;
+mod search;
+mod server;
+mod tui;
+
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    if args.len() > 1 && args[1] == "tui" {
+        tui::run(&args[2..]).expect("error running terminal viewer");
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "search" {
+        search::run(args.split_off(2));
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "nucleus" {
+        nucleus::run(args.split_off(2));
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "serve" {
+        let address = args.get(2).map_or("127.0.0.1:3000", |s| s.as_str());
+        server::run(address).expect("error running server");
+        return;
+    }
+
+    let precision = take_option(&mut args, "--precision")
+        .and_then(|value| value.map_or(Ok(Precision::F64), |value| value.parse()))
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
 
     if args.len() != 5 {
-        eprintln!("Usage: {} FILE PIXELS UPPERLEFT LOWERRIGHT", args[0]);
+        eprintln!(
+            "Usage: {} [--precision f32|f64|double-double] FILE PIXELS UPPERLEFT LOWERRIGHT",
+            args[0]
+        );
+        eprintln!("       {} tui [UPPERLEFT LOWERRIGHT]", args[0]);
+        eprintln!(
+            "       {} search [--count N] [--limit N] [UPPERLEFT LOWERRIGHT]",
+            args[0]
+        );
+        eprintln!(
+            "       {} nucleus [--max-period N] [--precision P] UPPERLEFT LOWERRIGHT",
+            args[0]
+        );
+        eprintln!("       {} serve [ADDRESS]", args[0]);
         eprintln!(
             "Example: {} mandel.png 1000x750 -1.20,0.35 -1,0.20",
             args[0]
@@ -19,10 +68,90 @@ fn main() {
     }
 
     let bounds = parse_pair(&args[2], 'x').expect("error parsing image dimentions");
-    let upper_left = parse_complex(&args[3]).expect("error parsing upper left coner point");
-    let lower_right = parse_complex(&args[4]).expect("error parsing lower right coner point");
 
     let mut pixels = vec![0; bounds.0 * bounds.1];
+    match precision {
+        Precision::F32 => render_region::<f32>(&mut pixels, bounds, &args[3], &args[4]),
+        Precision::F64 => render_region::<f64>(&mut pixels, bounds, &args[3], &args[4]),
+        Precision::DoubleDouble => {
+            render_region::<DoubleDouble>(&mut pixels, bounds, &args[3], &args[4])
+        }
+    }
+
+    write_image(&args[1], &pixels, bounds).expect("error writing PNG file");
+}
+
+/// The floating-point type used for points on the complex plane.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Precision {
+    /// Fast, but runs out of precision after a few zooms. Good for previews.
+    F32,
+    F64,
+    /// About 31 significant digits, at several times the cost of `F64`.
+    DoubleDouble,
+}
+
+impl FromStr for Precision {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Precision, String> {
+        match s {
+            "f32" => Ok(Precision::F32),
+            "f64" => Ok(Precision::F64),
+            "double-double" | "dd" => Ok(Precision::DoubleDouble),
+            _ => Err(format!(
+                "unknown precision '{}': expected f32, f64 or double-double",
+                s
+            )),
+        }
+    }
+}
+
+/// Remove the option `name` and its value from `args`, and return the value,
+/// or `None` if the option isn't given.
+///
+/// The value may be given either as the next argument (`--name value`) or
+/// after an equals sign (`--name=value`). An option with no value after it
+/// is an error.
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let prefix = format!("{}=", name);
+    let Some(index) = args
+        .iter()
+        .position(|arg| arg == name || arg.starts_with(&prefix))
+    else {
+        return Ok(None);
+    };
+
+    let arg = args.remove(index);
+    match arg.strip_prefix(&prefix) {
+        Some(value) => Ok(Some(value.to_string())),
+        None if index < args.len() => Ok(Some(args.remove(index))),
+        None => Err(format!("option '{}' needs a value", name)),
+    }
+}
+
+/// Parse the corners given on the command line as points of type `T`, and
+/// render the rectangle between them into `pixels`.
+fn render_region<T>(pixels: &mut [u8], bounds: (usize, usize), upper_left: &str, lower_right: &str)
+where
+    T: Float + FromStr + Send,
+{
+    let upper_left: Complex<T> =
+        parse_complex(upper_left).expect("error parsing upper left coner point");
+    let lower_right: Complex<T> =
+        parse_complex(lower_right).expect("error parsing lower right coner point");
+
+    render_parallel(pixels, bounds, upper_left, lower_right);
+}
+
+/// Like `render`, but split the buffer into horizontal bands and render
+/// each band on its own thread.
+fn render_parallel<T: Float + Send>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    upper_left: Complex<T>,
+    lower_right: Complex<T>,
+) {
     // render(&mut pixels, bounds, upper_left, lower_right);
     let threads = 8;
     // let threads = 8 * 2;
@@ -47,8 +176,6 @@ fn main() {
         })
         .unwrap();
     }
-
-    write_image(&args[1], &pixels, bounds).expect("error writing PNG file");
 }
 
 fn write_image(
@@ -70,17 +197,47 @@ fn write_image(
     Ok(())
 }
 
+/// Encode `pixels` as a PNG image to `output`. `color_type` says how the
+/// bytes of `pixels` are laid out, for example one byte per pixel for `L8`.
+fn write_png<W: Write>(
+    output: W,
+    pixels: &[u8],
+    bounds: (usize, usize),
+    color_type: ColorType,
+) -> ImageResult<()> {
+    let encoder = PngEncoder::new(output);
+    encoder.write_image(pixels, bounds.0 as u32, bounds.1 as u32, color_type)?;
+
+    Ok(())
+}
+
 /// Render a rectangle of the Mandelbrot set into a buffer of pixels.
 ///
 /// The `bounds` argument gives the width and height of the buffer `pixels`,
 /// which holds one grayscale pixel per byte. the `upper_left` and `lower_right`
 /// arguments specify points on the complex plane corresponding to the upper-
 /// left and lower-right corners of the pixel buffer.
-fn render(
+///
+/// `T` is the floating-point type used for the arithmetic: `f32`, `f64`, or
+/// `DoubleDouble` for deep zooms.
+fn render<T: Float>(
+    pixels: &mut [u8],
+    bounds: (usize, usize),
+    upper_left: Complex<T>,
+    lower_right: Complex<T>,
+) {
+    render_with_limit(pixels, bounds, upper_left, lower_right, 255);
+}
+
+/// Like `render`, but give up on each point after `limit` iterations rather
+/// than 255. Escape counts are scaled so that the full range of gray is used
+/// whatever the limit.
+fn render_with_limit<T: Float>(
     pixels: &mut [u8],
     bounds: (usize, usize),
-    upper_left: Complex<f64>,
-    lower_right: Complex<f64>,
+    upper_left: Complex<T>,
+    lower_right: Complex<T>,
+    limit: usize,
 ) {
     assert!(pixels.len() == bounds.0 * bounds.1);
 
@@ -89,14 +246,32 @@ fn render(
             let point = pixel_to_point(bounds, (column, row), upper_left, lower_right);
 
             // bounds.0 is width
-            pixels[row * bounds.0 + column] = match escape_time(point, 255) {
+            pixels[row * bounds.0 + column] = match escape_time(point, limit) {
                 None => 0,
-                Some(count) => 255 - count as u8,
+                Some(count) => 255 - (count * 255 / limit) as u8,
             };
         }
     }
 }
 
+/// Map a grayscale pixel produced by `render` to an RGB color.
+///
+/// Points in the set (zero) stay black; escaping points are spread over a
+/// smooth dark blue to orange gradient by how long they took to escape.
+fn gradient(value: u8) -> [u8; 3] {
+    if value == 0 {
+        return [0, 0, 0];
+    }
+
+    let t = (255 - value) as f64 / 255.0;
+    let channel = |x: f64| (x * 255.0).round().clamp(0.0, 255.0) as u8;
+    [
+        channel(9.0 * (1.0 - t) * t * t * t),
+        channel(15.0 * (1.0 - t) * (1.0 - t) * t * t),
+        channel(8.5 * (1.0 - t) * (1.0 - t) * (1.0 - t) * t),
+    ]
+}
+
 /// Given the row and column of a pixel in the output image, return the
 /// corresponding point on the complex plane.
 ///
@@ -104,25 +279,30 @@ fn render(
 /// `pixel` is a (column, row) pair indicating a particular pixel in that image.
 /// The `upper_left` and `lower_right` parameters are points on the complex
 /// plane designating the area our image covers.
-fn pixel_to_point(
+fn pixel_to_point<T: Float>(
     bounds: (usize, usize),
     pixel: (usize, usize),
-    upper_left: Complex<f64>,
-    lower_right: Complex<f64>,
-) -> Complex<f64> {
+    upper_left: Complex<T>,
+    lower_right: Complex<T>,
+) -> Complex<T> {
     let (width, height) = (
         lower_right.re - upper_left.re,
         upper_left.im - lower_right.im,
     );
 
     Complex {
-        re: upper_left.re + pixel.0 as f64 * width / bounds.0 as f64,
-        im: upper_left.im - pixel.1 as f64 * height / bounds.1 as f64,
+        re: upper_left.re + as_float::<T>(pixel.0) * width / as_float(bounds.0),
+        im: upper_left.im - as_float::<T>(pixel.1) * height / as_float(bounds.1),
         // Why substraction here? pixel.1 increases aswe go down,
         // but the imaginary component increases as we go up.
     }
 }
 
+/// Convert a pixel coordinate to the floating-point type `T`.
+fn as_float<T: Float>(n: usize) -> T {
+    T::from(n).expect("pixel coordinate out of range")
+}
+
 /// Parse the string `s` as a coordinate pair, like `"400x600"` or `"1.0,0.5"`.
 ///
 /// Specifically, `s` sould have the form <left><sep><right>, where <sep> is
@@ -144,7 +324,7 @@ fn parse_pair<T: FromStr>(s: &str, separator: char) -> Option<(T, T)> {
 }
 
 /// Parse a pair of floating-point numbers separated by a comma as a complex number.
-fn parse_complex(s: &str) -> Option<Complex<f64>> {
+fn parse_complex<T: FromStr>(s: &str) -> Option<Complex<T>> {
     match parse_pair(s, ',') {
         Some((re, im)) => Some(Complex { re, im }),
         None => None,
@@ -158,10 +338,11 @@ fn parse_complex(s: &str) -> Option<Complex<f64>> {
 /// origin. If `c` seems to be a member (more precisely, if we reached the
 /// iteration limit without being able to prove that `c` is not a member),
 /// return `None`
-fn escape_time(c: Complex<f64>, limit: usize) -> Option<usize> {
-    let mut z = Complex { re: 0.0, im: 0.0 };
+fn escape_time<T: Float>(c: Complex<T>, limit: usize) -> Option<usize> {
+    let mut z = Complex::new(T::zero(), T::zero());
+    let four = as_float::<T>(4);
     for i in 0..limit {
-        if z.norm_sqr() > 4.0 {
+        if z.norm_sqr() > four {
             return Some(i);
         }
         z = z * z + c
@@ -307,7 +488,7 @@ mod tests {
             })
         );
 
-        assert_eq!(parse_complex(",-0.0625"), None);
+        assert_eq!(parse_complex::<f64>(",-0.0625"), None);
     }
 
     #[test]
@@ -353,4 +534,63 @@ mod tests {
             assert_eq!(pixels, vec![252, 250, 252, 244, 0, 0, 244, 0, 0]);
         }
     }
+
+    #[test]
+    fn test_render_precisions() {
+        let upper_left = "-1.0,1.0";
+        let lower_right = "1.0,-1.0";
+        let expected = vec![252, 250, 252, 244, 0, 0, 244, 0, 0];
+
+        let mut pixels = vec![0; 9];
+        render_region::<f32>(&mut pixels, (3, 3), upper_left, lower_right);
+        assert_eq!(pixels, expected);
+
+        let mut pixels = vec![0; 9];
+        render_region::<DoubleDouble>(&mut pixels, (3, 3), upper_left, lower_right);
+        assert_eq!(pixels, expected);
+    }
+
+    #[test]
+    fn test_escape_time_double_double() {
+        let c: Complex<DoubleDouble> = parse_complex("2.0,2.0").unwrap();
+        assert_eq!(escape_time(c, 10), Some(1));
+
+        let c: Complex<DoubleDouble> = parse_complex("-1.0,0.0").unwrap();
+        assert_eq!(escape_time(c, 1000), None);
+    }
+
+    #[test]
+    fn test_parse_precision() {
+        assert_eq!("f32".parse(), Ok(Precision::F32));
+        assert_eq!("f64".parse(), Ok(Precision::F64));
+        assert_eq!("double-double".parse(), Ok(Precision::DoubleDouble));
+        assert_eq!("dd".parse(), Ok(Precision::DoubleDouble));
+        assert!("f128".parse::<Precision>().is_err());
+    }
+
+    #[test]
+    fn test_take_option() {
+        let to_args = |s: &str| -> Vec<String> { s.split(' ').map(String::from).collect() };
+
+        let mut args = to_args("mandelbrot --precision f32 out.png");
+        assert_eq!(
+            take_option(&mut args, "--precision"),
+            Ok(Some("f32".to_string()))
+        );
+        assert_eq!(args, to_args("mandelbrot out.png"));
+
+        let mut args = to_args("mandelbrot out.png --precision=dd");
+        assert_eq!(
+            take_option(&mut args, "--precision"),
+            Ok(Some("dd".to_string()))
+        );
+        assert_eq!(args, to_args("mandelbrot out.png"));
+
+        let mut args = to_args("mandelbrot out.png");
+        assert_eq!(take_option(&mut args, "--precision"), Ok(None));
+        assert_eq!(args, to_args("mandelbrot out.png"));
+
+        let mut args = to_args("mandelbrot out.png --precision");
+        assert!(take_option(&mut args, "--precision").is_err());
+    }
 }
@@ -0,0 +1,271 @@
+use crate::double_double::DoubleDouble;
+use crate::{as_float, parse_complex, take_option, Precision};
+use num::{Complex, Float};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Maximum number of Newton steps taken by `find_nucleus`.
+const NEWTON_STEPS: usize = 64;
+
+/// Find the period of the atom domain covering the rectangle between
+/// `upper_left` and `lower_right`, trying periods up to `max_period`.
+///
+/// We iterate `z = z * z + c` for the four corners of the rectangle at once.
+/// The first iteration at which the quadrilateral they form surrounds the
+/// origin is the period of the lowest-period nucleus inside the rectangle.
+/// Return `None` if no period up to `max_period` was found, or if the corners
+/// escaped so far that the test became meaningless.
+pub fn find_period<T: Float>(
+    upper_left: Complex<T>,
+    lower_right: Complex<T>,
+    max_period: usize,
+) -> Option<usize> {
+    let corners = [
+        upper_left,
+        Complex::new(lower_right.re, upper_left.im),
+        lower_right,
+        Complex::new(upper_left.re, lower_right.im),
+    ];
+
+    let mut z = [Complex::new(T::zero(), T::zero()); 4];
+    for period in 1..=max_period {
+        for (z, c) in z.iter_mut().zip(corners) {
+            *z = *z * *z + c;
+        }
+        if z.iter().any(|z| !z.re.is_finite() || !z.im.is_finite()) {
+            return None;
+        }
+        if surrounds_origin(&z) {
+            return Some(period);
+        }
+    }
+    None
+}
+
+/// Return true if the polygon with the given vertices contains the origin,
+/// by counting how many of its edges cross the positive real axis.
+fn surrounds_origin<T: Float>(polygon: &[Complex<T>]) -> bool {
+    let mut crossings = 0;
+    for (i, a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a.im > T::zero()) != (b.im > T::zero()) {
+            let x = a.re - a.im * (b.re - a.re) / (b.im - a.im);
+            if x > T::zero() {
+                crossings += 1;
+            }
+        }
+    }
+    crossings % 2 == 1
+}
+
+/// Refine `guess` to the nucleus of a component of the given `period`: the
+/// point `c` where iterating `z = z * z + c` from zero returns to zero after
+/// `period` steps.
+///
+/// This uses Newton's method on `f(c) = z_period(c)`, carrying the derivative
+/// with respect to `c` along with `z`. Return `None` if the iteration didn't
+/// converge. Note that nuclei of periods dividing `period` are solutions too,
+/// so a poor guess may land on one of those instead.
+pub fn find_nucleus<T: Float>(guess: Complex<T>, period: usize) -> Option<Complex<T>> {
+    let one = Complex::new(T::one(), T::zero());
+    let two = Complex::new(as_float::<T>(2), T::zero());
+    let tolerance = T::epsilon() * as_float(16);
+
+    let mut c = guess;
+    for _ in 0..NEWTON_STEPS {
+        let mut z = Complex::new(T::zero(), T::zero());
+        let mut dz = Complex::new(T::zero(), T::zero());
+        for _ in 0..period {
+            dz = two * z * dz + one;
+            z = z * z + c;
+        }
+
+        let step = z / dz;
+        c = c - step;
+        if !c.re.is_finite() || !c.im.is_finite() {
+            return None;
+        }
+        // Newton's method converges quadratically, so once a step is this
+        // small the next would be lost in rounding.
+        if step.norm_sqr() <= tolerance * tolerance * c.norm_sqr().max(T::one()) {
+            return Some(c);
+        }
+    }
+    None
+}
+
+/// Estimate the size of the minibrot with the given `nucleus` and `period`,
+/// as the width of a viewport that frames it.
+///
+/// This uses the size estimate from the derivative of the periodic orbit:
+/// about `4 / |b * l^2|`, where `l` is the product of `2 * z` along the orbit
+/// and `b` is the sum of the reciprocals of its partial products.
+pub fn minibrot_size<T: Float>(nucleus: Complex<T>, period: usize) -> T {
+    let one = Complex::new(T::one(), T::zero());
+    let two = Complex::new(as_float::<T>(2), T::zero());
+
+    let mut z = Complex::new(T::zero(), T::zero());
+    let mut l = one;
+    let mut b = one;
+    for _ in 1..period {
+        z = z * z + nucleus;
+        l = two * z * l;
+        b = b + one / l;
+    }
+
+    as_float::<T>(4) * (one / (b * l * l)).norm()
+}
+
+/// Run the `nucleus` subcommand.
+///
+/// `args` are the command-line arguments following `nucleus`. Print the
+/// nucleus, its period, the suggested zoom size, and the upper-left and
+/// lower-right corners of a square viewport of that size, tab-separated.
+pub fn run(mut args: Vec<String>) {
+    let usage = || -> ! {
+        eprintln!(
+            "Usage: mandelbrot nucleus [--max-period N] [--precision f32|f64|double-double] \
+             UPPERLEFT LOWERRIGHT"
+        );
+        std::process::exit(1);
+    };
+
+    let max_period = match take_option(&mut args, "--max-period") {
+        Ok(None) => 10_000,
+        Ok(Some(value)) => value.parse().unwrap_or_else(|_| usage()),
+        Err(_) => usage(),
+    };
+    let precision = take_option(&mut args, "--precision")
+        .and_then(|value| value.map_or(Ok(Precision::F64), |value| value.parse()))
+        .unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+
+    let [upper_left, lower_right] = &args[..] else {
+        usage()
+    };
+
+    match precision {
+        Precision::F32 => print_nucleus::<f32>(upper_left, lower_right, max_period),
+        Precision::F64 => print_nucleus::<f64>(upper_left, lower_right, max_period),
+        Precision::DoubleDouble => {
+            print_nucleus::<DoubleDouble>(upper_left, lower_right, max_period)
+        }
+    }
+}
+
+fn print_nucleus<T>(upper_left: &str, lower_right: &str, max_period: usize)
+where
+    T: Float + FromStr + Display,
+{
+    let upper_left: Complex<T> =
+        parse_complex(upper_left).expect("error parsing upper left coner point");
+    let lower_right: Complex<T> =
+        parse_complex(lower_right).expect("error parsing lower right coner point");
+
+    let Some(period) = find_period(upper_left, lower_right, max_period) else {
+        eprintln!("no period up to {} found in this viewport", max_period);
+        std::process::exit(2);
+    };
+
+    let guess = (upper_left + lower_right) / as_float::<T>(2);
+    let Some(nucleus) = find_nucleus(guess, period) else {
+        eprintln!("Newton's method did not converge for period {}", period);
+        std::process::exit(2);
+    };
+
+    let size = minibrot_size(nucleus, period);
+    let half = Complex::new(size, -size) / as_float::<T>(2);
+    let (corner, opposite) = (nucleus - half, nucleus + half);
+    println!(
+        "{},{}\t{}\t{}\t{},{}\t{},{}",
+        nucleus.re, nucleus.im, period, size, corner.re, corner.im, opposite.re, opposite.im
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Complex<f64>, b: Complex<f64>) {
+        assert!((a - b).norm() < 1e-12, "{} is not close to {}", a, b);
+    }
+
+    #[test]
+    fn test_find_period() {
+        let box_around =
+            |c: Complex<f64>, r: f64| (c + Complex::new(-r, r), c + Complex::new(r, -r));
+
+        let (ul, lr) = box_around(Complex::new(0.0, 0.0), 0.1);
+        assert_eq!(find_period(ul, lr, 100), Some(1));
+
+        let (ul, lr) = box_around(Complex::new(-1.0, 0.0), 0.05);
+        assert_eq!(find_period(ul, lr, 100), Some(2));
+
+        let (ul, lr) = box_around(Complex::new(-1.7549, 0.0), 0.001);
+        assert_eq!(find_period(ul, lr, 100), Some(3));
+
+        let (ul, lr) = box_around(Complex::new(-0.1226, 0.7449), 0.001);
+        assert_eq!(find_period(ul, lr, 100), Some(3));
+
+        let (ul, lr) = box_around(Complex::new(-1.3107, 0.0), 0.001);
+        assert_eq!(find_period(ul, lr, 100), Some(4));
+
+        // Far outside the set, the corners escape before any period is found.
+        let (ul, lr) = box_around(Complex::new(3.0, 3.0), 0.1);
+        assert_eq!(find_period(ul, lr, 100), None);
+    }
+
+    #[test]
+    fn test_find_nucleus() {
+        let known = [
+            (Complex::new(0.0, 0.0), 1),
+            (Complex::new(-1.0, 0.0), 2),
+            (Complex::new(-1.754877666246693, 0.0), 3),
+            (Complex::new(-0.12256116687665362, 0.7448617666197442), 3),
+            (Complex::new(-1.3107026413368328, 0.0), 4),
+            (Complex::new(-1.9407998065294848, 0.0), 4),
+        ];
+        for (nucleus, period) in known {
+            let guess = nucleus + Complex::new(1e-4, -1e-4);
+            assert_close(find_nucleus(guess, period).unwrap(), nucleus);
+        }
+    }
+
+    #[test]
+    fn test_find_nucleus_double_double() {
+        let guess: Complex<DoubleDouble> = parse_complex("-1.7549,0.0").unwrap();
+        let nucleus = find_nucleus(guess, 3).unwrap();
+
+        // The period-3 nucleus is the real root of c^3 + 2c^2 + c + 1.
+        let c = nucleus.re;
+        let (one, two) = (as_float::<DoubleDouble>(1), as_float::<DoubleDouble>(2));
+        let residual = c * c * c + two * c * c + c + one;
+        assert!(residual.abs() < DoubleDouble::epsilon() * as_float(64));
+        assert!(nucleus.im.abs() < DoubleDouble::epsilon());
+    }
+
+    #[test]
+    fn test_minibrot_size() {
+        // The period-2 disk has radius 1/4.
+        assert_eq!(minibrot_size(Complex::new(-1.0, 0.0), 2), 2.0);
+
+        let size = minibrot_size(Complex::new(-1.754877666246693, 0.0), 3);
+        assert!(0.01 < size && size < 0.1, "unexpected size {}", size);
+    }
+
+    #[test]
+    fn test_surrounds_origin() {
+        let square = [
+            Complex::new(-1.0, 1.0),
+            Complex::new(1.0, 1.0),
+            Complex::new(1.0, -1.0),
+            Complex::new(-1.0, -1.0),
+        ];
+        assert!(surrounds_origin(&square));
+
+        let shifted: Vec<_> = square.iter().map(|z| z + Complex::new(2.5, 0.0)).collect();
+        assert!(!surrounds_origin(&shifted));
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{escape_time, parse_complex, pixel_to_point, take_option};
+use num::Complex;
+
+/// Width in samples of the grid laid over the region being searched. The
+/// height follows the aspect ratio of the region.
+const GRID_WIDTH: usize = 256;
+
+/// Most samples the grid may be tall, however narrow the region.
+const MAX_GRID_HEIGHT: usize = 4 * GRID_WIDTH;
+
+/// Width and height in samples of the tiles the grid is divided into. Each
+/// tile yields at most one candidate.
+const TILE: usize = 8;
+
+/// A point worth zooming into, found by `find_interesting_points`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Candidate {
+    pub center: Complex<f64>,
+    /// Width of the complex plane around `center` that the candidate covers.
+    pub width: f64,
+    /// Variance of the escape counts around `center`; higher means more
+    /// detail.
+    pub score: f64,
+}
+
+impl Candidate {
+    /// Return the upper-left and lower-right corners of a square viewport of
+    /// the candidate's width, centered on it.
+    pub fn corners(&self) -> (Complex<f64>, Complex<f64>) {
+        let half = Complex::new(self.width / 2.0, -self.width / 2.0);
+        (self.center - half, self.center + half)
+    }
+}
+
+/// Search the rectangle between `upper_left` and `lower_right` for points on
+/// the boundary of the Mandelbrot set with a lot of local detail, and return
+/// at most `count` of them, best first.
+///
+/// The rectangle is sampled on a grid, which is divided into tiles. Only tiles
+/// that contain both members and non-members of the set lie on the boundary;
+/// those are scored by the variance of the escape counts of their escaping
+/// samples. Each candidate is centered on the escaping sample in its tile that
+/// took longest to escape, which is where the filaments are.
+pub fn find_interesting_points(
+    upper_left: Complex<f64>,
+    lower_right: Complex<f64>,
+    limit: usize,
+    count: usize,
+) -> Vec<Candidate> {
+    let width = lower_right.re - upper_left.re;
+    let height = upper_left.im - lower_right.im;
+    let grid_height =
+        ((GRID_WIDTH as f64 * height / width).round() as usize).clamp(TILE, MAX_GRID_HEIGHT);
+    let bounds = (GRID_WIDTH, grid_height);
+
+    let counts: Vec<Option<usize>> = (0..bounds.0 * bounds.1)
+        .map(|i| {
+            let point = pixel_to_point(
+                bounds,
+                (i % bounds.0, i / bounds.0),
+                upper_left,
+                lower_right,
+            );
+            escape_time(point, limit)
+        })
+        .collect();
+
+    let mut candidates = vec![];
+    for tile_top in (0..bounds.1 - TILE + 1).step_by(TILE) {
+        for tile_left in (0..bounds.0 - TILE + 1).step_by(TILE) {
+            let mut escaped = vec![];
+            let mut members = 0;
+            for row in tile_top..tile_top + TILE {
+                for column in tile_left..tile_left + TILE {
+                    match counts[row * bounds.0 + column] {
+                        None => members += 1,
+                        Some(n) => escaped.push((n, (column, row))),
+                    }
+                }
+            }
+            if members == 0 || escaped.is_empty() {
+                continue;
+            }
+
+            let &(_, deepest) = escaped.iter().max_by_key(|&&(n, _)| n).unwrap();
+            candidates.push(Candidate {
+                center: pixel_to_point(bounds, deepest, upper_left, lower_right),
+                width: width * TILE as f64 / bounds.0 as f64,
+                score: variance(escaped.iter().map(|&(n, _)| n as f64)),
+            });
+        }
+    }
+
+    candidates.sort_by(|a, b| b.score.total_cmp(&a.score));
+    candidates.truncate(count);
+    candidates
+}
+
+/// Return the population variance of `values`, or zero if there are none.
+fn variance(values: impl Iterator<Item = f64> + Clone) -> f64 {
+    let n = values.clone().count() as f64;
+    if n == 0.0 {
+        return 0.0;
+    }
+    let mean = values.clone().sum::<f64>() / n;
+    values.map(|x| (x - mean) * (x - mean)).sum::<f64>() / n
+}
+
+/// Run the `search` subcommand.
+///
+/// `args` are the command-line arguments following `search`. Print one
+/// candidate per line, best first, as tab-separated rank, center, score,
+/// upper-left and lower-right corners, so the corners can be passed straight
+/// back to `mandelbrot FILE PIXELS UPPERLEFT LOWERRIGHT`.
+pub fn run(mut args: Vec<String>) {
+    let usage = || -> ! {
+        eprintln!("Usage: mandelbrot search [--count N] [--limit N] [UPPERLEFT LOWERRIGHT]");
+        std::process::exit(1);
+    };
+
+    let mut number_option = |name, default| match take_option(&mut args, name) {
+        Ok(None) => default,
+        Ok(Some(value)) => value.parse().unwrap_or_else(|_| usage()),
+        Err(_) => usage(),
+    };
+    let count = number_option("--count", 10);
+    let limit = number_option("--limit", 1000);
+
+    let (upper_left, lower_right): (Complex<f64>, Complex<f64>) = match &args[..] {
+        [] => (Complex::new(-2.5, 1.25), Complex::new(1.0, -1.25)),
+        [upper_left, lower_right] => (
+            parse_complex(upper_left).expect("error parsing upper left coner point"),
+            parse_complex(lower_right).expect("error parsing lower right coner point"),
+        ),
+        _ => usage(),
+    };
+    let width = lower_right.re - upper_left.re;
+    let height = upper_left.im - lower_right.im;
+    if !(width.is_finite() && width > 0.0 && height.is_finite() && height > 0.0) {
+        eprintln!("The upper left corner must be above and to the left of the lower right one.");
+        std::process::exit(1);
+    }
+
+    for (rank, candidate) in find_interesting_points(upper_left, lower_right, limit, count)
+        .iter()
+        .enumerate()
+    {
+        let (upper_left, lower_right) = candidate.corners();
+        println!(
+            "{}\t{},{}\t{:.1}\t{},{}\t{},{}",
+            rank + 1,
+            candidate.center.re,
+            candidate.center.im,
+            candidate.score,
+            upper_left.re,
+            upper_left.im,
+            lower_right.re,
+            lower_right.im
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_interesting_points() {
+        let candidates =
+            find_interesting_points(Complex::new(-2.5, 1.25), Complex::new(1.0, -1.25), 200, 5);
+        assert_eq!(candidates.len(), 5);
+
+        for pair in candidates.windows(2) {
+            assert!(pair[0].score >= pair[1].score);
+        }
+        for candidate in &candidates {
+            // Centers are escaping points, but close enough to the set to
+            // take more than a couple of iterations.
+            let escape = escape_time(candidate.center, 200).unwrap();
+            assert!(escape > 2);
+            assert!(candidate.score > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_find_interesting_points_inside_set() {
+        // Entirely inside the main cardioid: no boundary, no candidates.
+        let candidates =
+            find_interesting_points(Complex::new(-0.1, 0.1), Complex::new(0.1, -0.1), 200, 5);
+        assert_eq!(candidates, vec![]);
+    }
+
+    #[test]
+    fn test_find_interesting_points_narrow() {
+        // A sliver a billionth as wide as it is tall still gets a grid of
+        // bounded size.
+        let candidates =
+            find_interesting_points(Complex::new(0.0, 1.0), Complex::new(1e-9, -1.0), 50, 5);
+        assert!(candidates.len() <= 5);
+        for candidate in &candidates {
+            assert!(candidate.width > 0.0 && candidate.width < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_candidate_corners() {
+        let candidate = Candidate {
+            center: Complex::new(-0.5, 0.25),
+            width: 0.5,
+            score: 1.0,
+        };
+        assert_eq!(
+            candidate.corners(),
+            (Complex::new(-0.75, 0.5), Complex::new(-0.25, 0.0))
+        );
+    }
+
+    #[test]
+    fn test_variance() {
+        assert_eq!(variance([].into_iter()), 0.0);
+        assert_eq!(
+            variance([2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0].into_iter()),
+            4.0
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use crate::{gradient, pixel_to_point, render_with_limit, write_png};
+use actix_web::{web, App, HttpResponse, HttpServer};
+use image::{ColorType, ImageResult};
+use num::Complex;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use serde::Deserialize;
+
+/// Largest image, in pixels, that a single request may ask for.
+const MAX_PIXELS: usize = 4_000_000;
+
+/// Largest iteration limit that a single request may ask for.
+const MAX_LIMIT: usize = 10_000;
+
+/// How escape counts are turned into pixel colors.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Palette {
+    #[default]
+    Grayscale,
+    Gradient,
+}
+
+/// The JSON body of a `POST /render` request.
+#[derive(Debug, Deserialize)]
+struct RenderParameters {
+    /// `[re, im]` of the upper-left corner of the image.
+    upper_left: (f64, f64),
+    /// `[re, im]` of the lower-right corner of the image.
+    lower_right: (f64, f64),
+    width: usize,
+    height: usize,
+    #[serde(default = "default_limit")]
+    limit: usize,
+    #[serde(default)]
+    palette: Palette,
+}
+
+fn default_limit() -> usize {
+    255
+}
+
+impl RenderParameters {
+    /// Check that the request is within the limits this server is willing to
+    /// render. If not, return a message explaining why.
+    fn validate(&self) -> Result<(), String> {
+        if self.width == 0 || self.height == 0 {
+            return Err("The image must be at least one pixel wide and tall.".to_string());
+        }
+        match self.width.checked_mul(self.height) {
+            Some(pixels) if pixels <= MAX_PIXELS => {}
+            _ => return Err(format!("The image may have at most {} pixels.", MAX_PIXELS)),
+        }
+        if self.limit == 0 || self.limit > MAX_LIMIT {
+            return Err(format!(
+                "The iteration limit must be between 1 and {}.",
+                MAX_LIMIT
+            ));
+        }
+
+        let (left, top) = self.upper_left;
+        let (right, bottom) = self.lower_right;
+        if ![left, top, right, bottom].iter().all(|x| x.is_finite()) {
+            return Err("The corners must be finite numbers.".to_string());
+        }
+        if left >= right || bottom >= top {
+            return Err(
+                "The upper left corner must be above and left of the lower right one.".to_string(),
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Serve the rendering API on `address` until the process is stopped.
+///
+/// All requests share one pool of rendering threads, one per CPU, so that
+/// concurrent requests queue up for CPU time rather than oversubscribing it.
+pub fn run(address: &str) -> std::io::Result<()> {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .build()
+        .map_err(std::io::Error::other)?;
+    let pool = web::Data::new(pool);
+
+    actix_web::rt::System::new().block_on(async {
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(pool.clone())
+                .route("/render", web::post().to(post_render))
+        });
+
+        println!("Serving on http://{}", address);
+
+        server.bind(address)?.run().await
+    })
+}
+
+async fn post_render(
+    pool: web::Data<ThreadPool>,
+    web::Json(params): web::Json<RenderParameters>,
+) -> HttpResponse {
+    if let Err(message) = params.validate() {
+        return HttpResponse::BadRequest()
+            .content_type("text/plain")
+            .body(message);
+    }
+
+    // Waiting for the pool blocks, so do it off the async worker thread.
+    let png = web::block(move || pool.install(|| render_png(&params))).await;
+
+    match png {
+        Ok(Ok(png)) => HttpResponse::Ok().content_type("image/png").body(png),
+        Ok(Err(e)) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("Error encoding PNG: {}", e)),
+        Err(e) => HttpResponse::InternalServerError()
+            .content_type("text/plain")
+            .body(format!("Error rendering image: {}", e)),
+    }
+}
+
+/// Render the image described by `params` and encode it as a PNG. Rows are
+/// rendered in parallel on the current rayon pool.
+fn render_png(params: &RenderParameters) -> ImageResult<Vec<u8>> {
+    let bounds = (params.width, params.height);
+    let upper_left = Complex::new(params.upper_left.0, params.upper_left.1);
+    let lower_right = Complex::new(params.lower_right.0, params.lower_right.1);
+
+    let mut pixels = vec![0; bounds.0 * bounds.1];
+    pixels
+        .par_chunks_mut(bounds.0)
+        .enumerate()
+        .for_each(|(row, band)| {
+            let band_upper_left = pixel_to_point(bounds, (0, row), upper_left, lower_right);
+            let band_lower_right =
+                pixel_to_point(bounds, (bounds.0, row + 1), upper_left, lower_right);
+            render_with_limit(
+                band,
+                (bounds.0, 1),
+                band_upper_left,
+                band_lower_right,
+                params.limit,
+            );
+        });
+
+    let mut png = vec![];
+    match params.palette {
+        Palette::Grayscale => write_png(&mut png, &pixels, bounds, ColorType::L8)?,
+        Palette::Gradient => {
+            let rgb: Vec<u8> = pixels.iter().flat_map(|&value| gradient(value)).collect();
+            write_png(&mut png, &rgb, bounds, ColorType::Rgb8)?
+        }
+    }
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::{header, StatusCode};
+    use actix_web::test;
+    use actix_web::web::Bytes;
+
+    async fn post(body: &str) -> (StatusCode, String, Bytes) {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .route("/render", web::post().to(post_render)),
+        )
+        .await;
+
+        let request = test::TestRequest::post()
+            .uri("/render")
+            .insert_header(header::ContentType::json())
+            .set_payload(body.to_string())
+            .to_request();
+        let response = test::call_service(&app, request).await;
+
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map_or(String::new(), |v| v.to_str().unwrap().to_string());
+        (status, content_type, test::read_body(response).await)
+    }
+
+    #[actix_web::test]
+    async fn test_render_grayscale() {
+        let (status, content_type, body) = post(
+            r#"{"upper_left": [-1.0, 1.0], "lower_right": [1.0, -1.0], "width": 3, "height": 3}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "image/png");
+
+        let image = image::load_from_memory(&body).unwrap().into_luma8();
+        assert_eq!(image.dimensions(), (3, 3));
+        assert_eq!(image.into_raw(), vec![252, 250, 252, 244, 0, 0, 244, 0, 0]);
+    }
+
+    #[actix_web::test]
+    async fn test_render_gradient() {
+        let (status, _, body) = post(
+            r#"{"upper_left": [-2.5, 1.0], "lower_right": [1.0, -1.0],
+                "width": 35, "height": 20, "limit": 1000, "palette": "gradient"}"#,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let image = image::load_from_memory(&body).unwrap();
+        assert_eq!(image.color(), ColorType::Rgb8);
+        assert_eq!((image.width(), image.height()), (35, 20));
+    }
+
+    #[actix_web::test]
+    async fn test_render_rejects_abuse() {
+        for body in [
+            r#"{"upper_left": [-1.0, 1.0], "lower_right": [1.0, -1.0], "width": 5000, "height": 5000}"#,
+            r#"{"upper_left": [-1.0, 1.0], "lower_right": [1.0, -1.0], "width": 0, "height": 10}"#,
+            r#"{"upper_left": [-1.0, 1.0], "lower_right": [1.0, -1.0], "width": 10, "height": 10, "limit": 1000000}"#,
+            r#"{"upper_left": [1.0, -1.0], "lower_right": [-1.0, 1.0], "width": 10, "height": 10}"#,
+            r#"{"upper_left": [-1.0, 1.0], "lower_right": [1.0, -1.0], "width": 10}"#,
+            r#"{"upper_left": [-1.0, 1.0], "lower_right": [1.0, -1.0], "width": 10, "height": 10, "palette": "plaid"}"#,
+        ] {
+            let (status, _, _) = post(body).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        }
+    }
+}
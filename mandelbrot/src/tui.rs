@@ -0,0 +1,267 @@
+use crate::{gradient, parse_complex, render};
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use num::Complex;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// Fraction of the view width moved by one arrow key press.
+const PAN_STEP: f64 = 0.1;
+
+/// Factor applied to the view width by one `+` key press.
+const ZOOM_STEP: f64 = 0.8;
+
+/// The part of the complex plane shown in the terminal.
+///
+/// We keep a center and a width rather than two corners, so that the height
+/// can follow the aspect ratio of the terminal when it is resized.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct View {
+    center: Complex<f64>,
+    width: f64,
+}
+
+impl View {
+    /// Return the upper-left and lower-right corners of this view for a pixel
+    /// buffer with the given `bounds`.
+    fn corners(&self, bounds: (usize, usize)) -> (Complex<f64>, Complex<f64>) {
+        let height = self.width * bounds.1 as f64 / bounds.0 as f64;
+        let half = Complex::new(self.width / 2.0, -height / 2.0);
+        (self.center - half, self.center + half)
+    }
+
+    /// Move the view by `dx` and `dy`, given as fractions of its width.
+    /// Positive `dy` moves the view up.
+    fn pan(&mut self, dx: f64, dy: f64) {
+        self.center += Complex::new(dx, dy) * self.width;
+    }
+
+    /// Scale the view width by `factor`; values below one zoom in.
+    fn zoom(&mut self, factor: f64) {
+        self.width *= factor;
+    }
+}
+
+/// A rendered pixel buffer, along with the bounds and view it was rendered for.
+struct Frame {
+    view: View,
+    bounds: (usize, usize),
+    pixels: Vec<u8>,
+}
+
+/// Run the interactive terminal viewer.
+///
+/// `args` are the command-line arguments following `tui`: either nothing, to
+/// show the whole set, or the `UPPERLEFT` and `LOWERRIGHT` corners to start at.
+pub fn run(args: &[String]) -> io::Result<()> {
+    let view = match args {
+        [] => View {
+            center: Complex::new(-0.5, 0.0),
+            width: 3.5,
+        },
+        [upper_left, lower_right] => {
+            let upper_left: Complex<f64> =
+                parse_complex(upper_left).expect("error parsing upper left coner point");
+            let lower_right: Complex<f64> =
+                parse_complex(lower_right).expect("error parsing lower right coner point");
+            View {
+                center: (upper_left + lower_right) / 2.0,
+                width: lower_right.re - upper_left.re,
+            }
+        }
+        _ => {
+            eprintln!("Usage: mandelbrot tui [UPPERLEFT LOWERRIGHT]");
+            std::process::exit(1);
+        }
+    };
+
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, Hide)?;
+
+    let result = event_loop(&mut out, view);
+
+    execute!(out, ResetColor, Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    result
+}
+
+fn event_loop(out: &mut impl Write, mut view: View) -> io::Result<()> {
+    let (frame_sender, frames) = mpsc::channel();
+    let requests = spawn_renderer(frame_sender);
+    let disconnected = || io::Error::other("renderer thread exited");
+
+    let mut bounds = buffer_bounds(terminal::size()?);
+    requests.send((view, bounds)).map_err(|_| disconnected())?;
+
+    loop {
+        // Only draw frames that still match the terminal size; anything
+        // older has already been superseded by a new request.
+        while let Ok(frame) = frames.try_recv() {
+            if frame.bounds == bounds {
+                draw(out, &frame)?;
+            }
+        }
+
+        if !event::poll(Duration::from_millis(50))? {
+            continue;
+        }
+
+        match event::read()? {
+            Event::Key(KeyEvent {
+                code,
+                modifiers,
+                kind: KeyEventKind::Press,
+                ..
+            }) => match code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                KeyCode::Left => view.pan(-PAN_STEP, 0.0),
+                KeyCode::Right => view.pan(PAN_STEP, 0.0),
+                KeyCode::Up => view.pan(0.0, PAN_STEP),
+                KeyCode::Down => view.pan(0.0, -PAN_STEP),
+                KeyCode::Char('+') | KeyCode::Char('=') => view.zoom(ZOOM_STEP),
+                KeyCode::Char('-') => view.zoom(1.0 / ZOOM_STEP),
+                _ => continue,
+            },
+            Event::Resize(columns, rows) => bounds = buffer_bounds((columns, rows)),
+            _ => continue,
+        }
+
+        requests.send((view, bounds)).map_err(|_| disconnected())?;
+    }
+}
+
+/// Start a thread that renders each requested view and sends the result to
+/// `frames`. Return the sender used to make requests.
+///
+/// If several requests queue up while a frame is being rendered, only the
+/// latest one is rendered; the others are already out of date.
+fn spawn_renderer(frames: Sender<Frame>) -> Sender<(View, (usize, usize))> {
+    let (sender, requests): (_, Receiver<(View, (usize, usize))>) = mpsc::channel();
+
+    thread::spawn(move || {
+        while let Ok(mut request) = requests.recv() {
+            while let Ok(newer) = requests.try_recv() {
+                request = newer;
+            }
+
+            let (view, bounds) = request;
+            let (upper_left, lower_right) = view.corners(bounds);
+            let mut pixels = vec![0; bounds.0 * bounds.1];
+            render(&mut pixels, bounds, upper_left, lower_right);
+
+            if frames
+                .send(Frame {
+                    view,
+                    bounds,
+                    pixels,
+                })
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    sender
+}
+
+/// Return the pixel buffer bounds for a terminal of the given size.
+///
+/// Each character cell shows two pixels stacked vertically, and the last row
+/// is kept for the status line.
+fn buffer_bounds((columns, rows): (u16, u16)) -> (usize, usize) {
+    let columns = (columns as usize).max(1);
+    let rows = (rows as usize).saturating_sub(1).max(1);
+    (columns, rows * 2)
+}
+
+/// Draw `frame` using upper half block characters, with the foreground color
+/// giving the top pixel and the background color giving the bottom one.
+fn draw(out: &mut impl Write, frame: &Frame) -> io::Result<()> {
+    let (width, height) = frame.bounds;
+
+    for row in 0..height / 2 {
+        queue!(out, MoveTo(0, row as u16))?;
+        for column in 0..width {
+            let top = frame.pixels[2 * row * width + column];
+            let bottom = frame.pixels[(2 * row + 1) * width + column];
+            queue!(
+                out,
+                SetForegroundColor(color(top)),
+                SetBackgroundColor(color(bottom)),
+                Print('\u{2580}')
+            )?;
+        }
+    }
+
+    let status = format!(
+        "{:.8},{:.8} width {:.3e}  arrows: pan  +/-: zoom  q: quit",
+        frame.view.center.re, frame.view.center.im, frame.view.width
+    );
+    queue!(
+        out,
+        ResetColor,
+        MoveTo(0, (height / 2) as u16),
+        Clear(ClearType::CurrentLine),
+        Print(status)
+    )?;
+
+    out.flush()
+}
+
+/// Map a grayscale pixel produced by `render` to a 24-bit terminal color.
+fn color(value: u8) -> Color {
+    let [r, g, b] = gradient(value);
+    Color::Rgb { r, g, b }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_corners() {
+        let view = View {
+            center: Complex::new(-0.5, 0.0),
+            width: 4.0,
+        };
+        assert_eq!(
+            view.corners((100, 50)),
+            (Complex::new(-2.5, 1.0), Complex::new(1.5, -1.0))
+        );
+    }
+
+    #[test]
+    fn test_view_pan_and_zoom() {
+        let mut view = View {
+            center: Complex::new(0.0, 0.0),
+            width: 2.0,
+        };
+
+        view.pan(0.5, -0.25);
+        assert_eq!(view.center, Complex::new(1.0, -0.5));
+
+        view.zoom(0.5);
+        assert_eq!(view.width, 1.0);
+        assert_eq!(view.center, Complex::new(1.0, -0.5));
+    }
+
+    #[test]
+    fn test_buffer_bounds() {
+        assert_eq!(buffer_bounds((80, 25)), (80, 48));
+        assert_eq!(buffer_bounds((0, 0)), (1, 2));
+    }
+
+    #[test]
+    fn test_color() {
+        assert_eq!(color(0), Color::Rgb { r: 0, g: 0, b: 0 });
+        assert_ne!(color(128), Color::Rgb { r: 0, g: 0, b: 0 });
+    }
+}
@@ -0,0 +1,144 @@
+/// The ways of writing a multi-word identifier that `--preserve-case` and
+/// replacement templates know.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Case {
+    Snake,
+    Screaming,
+    Kebab,
+    Camel,
+    Pascal,
+}
+
+const CASES: [Case; 5] = [
+    Case::Snake,
+    Case::Screaming,
+    Case::Kebab,
+    Case::Camel,
+    Case::Pascal,
+];
+
+impl Case {
+    /// Rewrite the identifier `text`, written in any case, in this one.
+    pub fn convert(self, text: &str) -> String {
+        self.join(&split_words(text))
+    }
+
+    /// Join the lowercase `words` into one identifier in this case.
+    fn join(self, words: &[String]) -> String {
+        match self {
+            Case::Snake => words.join("_"),
+            Case::Screaming => words.join("_").to_uppercase(),
+            Case::Kebab => words.join("-"),
+            Case::Camel => {
+                let mut joined = words.first().cloned().unwrap_or_default();
+                for word in &words[1.min(words.len())..] {
+                    joined.push_str(&capitalize(word));
+                }
+                joined
+            }
+            Case::Pascal => words.iter().map(|word| capitalize(word)).collect(),
+        }
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Split an identifier written in any of the cases above into its words, in
+/// lowercase. Words are separated by `_`, `-`, whitespace, or a change from
+/// lowercase to uppercase; in a run of capitals like `HTTPServer`, the last
+/// one starts a new word.
+pub fn split_words(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = vec![];
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '_' || c == '-' || c.is_whitespace() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        if c.is_uppercase() && !word.is_empty() {
+            let previous = chars[i - 1];
+            let next_is_lower = chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if !previous.is_uppercase() || next_is_lower {
+                words.push(std::mem::take(&mut word));
+            }
+        }
+        word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Return the spellings of `target` to look for, each paired with the
+/// spelling of `replacement` in the same case: `target` and `replacement` as
+/// given, then in snake_case, SCREAMING_CASE, kebab-case, camelCase and
+/// PascalCase.
+///
+/// Where two cases spell `target` the same, as all but SCREAMING_CASE and
+/// PascalCase do for a single word, the first one listed wins.
+pub fn variants(target: &str, replacement: &str) -> Vec<(String, String)> {
+    let (from, to) = (split_words(target), split_words(replacement));
+    let mut variants = vec![(target.to_string(), replacement.to_string())];
+    for case in CASES {
+        let spelling = case.join(&from);
+        if !variants.iter().any(|(seen, _)| *seen == spelling) {
+            variants.push((spelling, case.join(&to)));
+        }
+    }
+    variants
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_words() {
+        for text in [
+            "user_id", "USER_ID", "user-id", "userId", "UserId", "user id",
+        ] {
+            assert_eq!(split_words(text), ["user", "id"], "{}", text);
+        }
+        assert_eq!(split_words("HTTPServer2Go"), ["http", "server2", "go"]);
+        assert_eq!(split_words("__x__"), ["x"]);
+        assert!(split_words("").is_empty());
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(Case::Snake.convert("HTTPServer"), "http_server");
+        assert_eq!(Case::Camel.convert("user-id"), "userId");
+        assert_eq!(Case::Screaming.convert("userId"), "USER_ID");
+    }
+
+    #[test]
+    fn test_variants() {
+        assert_eq!(
+            variants("user_id", "account_id"),
+            [
+                ("user_id", "account_id"),
+                ("USER_ID", "ACCOUNT_ID"),
+                ("user-id", "account-id"),
+                ("userId", "accountId"),
+                ("UserId", "AccountId"),
+            ]
+            .map(|(a, b)| (a.to_string(), b.to_string()))
+        );
+
+        assert_eq!(
+            variants("user", "member"),
+            [("user", "member"), ("USER", "MEMBER"), ("User", "Member")]
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+        );
+    }
+}
@@ -0,0 +1,925 @@
+//! The `quickreplace` command line, built on the rest of the library.
+
+use crate::encoding::Decoding;
+use crate::error::QuickReplaceError;
+use crate::matcher::Matcher;
+use crate::replacement::Replacement;
+use crate::report::{Edit, Report};
+use crate::rules::{self, Rule, RuleSpec};
+use crate::{context, encoding, in_place, scope, stream, walk};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use error::CliError;
+use rayon::prelude::*;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use text_colorizer::*;
+
+mod diff;
+mod error;
+mod interactive;
+mod journal;
+mod watch;
+
+/// Change occurrences of one string into another.
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    after_help = "\
+Replacement functions, as in ${1:upper}, may be chained, as in ${1:snake:upper}:
+  upper, lower                            change letter case
+  snake, screaming, kebab, camel, pascal  rewrite an identifier in that case
+  inc, dec                                add or subtract one, keeping leading zeros
+
+Exit status:
+  0  success
+  1  invalid arguments, globs or rules file
+  2  an input file couldn't be read or watched
+  3  an output file couldn't be written
+  4  invalid regular expression
+  5  a dry run found changes to make
+  6  a run can't be undone because its files have changed since"
+)]
+struct Arguments {
+    /// Revert the changes made by an earlier --in-place run in this
+    /// directory, unless the files have changed since: RUN_ID, as shown when
+    /// it finished, or else the latest run not yet undone.
+    #[arg(
+        long,
+        value_name = "RUN_ID",
+        num_args = 0..=1,
+        conflicts_with_all = ["target", "rules", "in_place", "dry_run", "stream"]
+    )]
+    undo: Option<Option<String>>,
+    /// The regular expression to look for. Not given with --rules.
+    target: Option<String>,
+    /// What to replace each match with. It may refer to capture groups as
+    /// $1 or $name, transform them with ${1:upper}, ${name:snake} or
+    /// ${2:inc}, and number the matches with ${n}. Not given with --rules.
+    replacement: Option<String>,
+    /// The input file and the output file, either of which may be - for
+    /// standard input or output. With --in-place or --dry-run, any number of
+    /// files and directories to edit.
+    #[arg(value_name = "PATH")]
+    paths: Vec<String>,
+    /// Where to write the result when not editing in place or doing a dry
+    /// run. Taken from the end of `paths` by `parse_args`.
+    #[arg(skip)]
+    output: Option<String>,
+    /// Where to record what --in-place runs change, for --undo.
+    #[arg(skip = PathBuf::from(journal::DIR))]
+    journal_dir: PathBuf,
+    /// Apply the rules in FILE, in order, instead of a single target and
+    /// replacement. The flags given on the command line apply to every rule.
+    #[arg(long, value_name = "FILE", conflicts_with = "stream")]
+    rules: Option<PathBuf>,
+    /// Edit the files under the given paths in place.
+    #[arg(long)]
+    in_place: bool,
+    /// Print a diff of the changes to the files under the given paths instead
+    /// of making them.
+    #[arg(long, visible_alias = "diff")]
+    dry_run: bool,
+    /// Show each match and ask whether to replace it.
+    #[arg(long, conflicts_with_all = ["dry_run", "stream", "bytes", "rules"])]
+    interactive: bool,
+    /// Match the target and insert the replacement literally, rather than as
+    /// a regular expression and a template.
+    #[arg(short = 'F', long)]
+    fixed_strings: bool,
+    /// Match case-insensitively.
+    #[arg(short = 'i', long)]
+    ignore_case: bool,
+    /// Only match whole words.
+    #[arg(short = 'w', long)]
+    word_regexp: bool,
+    /// Let matches span lines: . matches newlines too, and ^ and $ match at
+    /// the start and end of every line.
+    #[arg(short = 'm', long)]
+    multiline: bool,
+    /// Treat the target as a literal identifier, and also replace it where
+    /// it's written in snake_case, SCREAMING_CASE, kebab-case, camelCase or
+    /// PascalCase, with the replacement written the same way.
+    #[arg(long, conflicts_with = "ignore_case")]
+    preserve_case: bool,
+    /// Only replace matches in code, comments or string literals, as found
+    /// in Rust, C-like, Python and shell files by their extension. Files in
+    /// other languages are taken to be all code.
+    #[arg(long, value_enum, value_name = "KIND", conflicts_with = "stream")]
+    scope: Option<scope::Scope>,
+    /// Only replace matches within these lines, counting from 1, like 10:200,
+    /// 10: or :200.
+    #[arg(long, value_name = "START:END", conflicts_with = "stream")]
+    lines: Option<context::LineRange>,
+    /// Only replace matches with a match for PATTERN in the lines before
+    /// them; see --within.
+    #[arg(long, value_name = "PATTERN", conflicts_with = "stream")]
+    before: Option<String>,
+    /// Only replace matches with a match for PATTERN in the lines after them;
+    /// see --within.
+    #[arg(long, value_name = "PATTERN", conflicts_with = "stream")]
+    after: Option<String>,
+    /// How many lines --before and --after look at, besides the lines the
+    /// match is on. Defaults to 5.
+    #[arg(long, value_name = "N")]
+    within: Option<usize>,
+    /// Match bytes rather than text, so that files need not be valid UTF-8.
+    /// Use (?-u) in the target to let . match any byte.
+    #[arg(long)]
+    bytes: bool,
+    /// Replace line by line as the input is read, to handle files too big
+    /// for memory. Implies --bytes; matches can't span lines.
+    #[arg(long, conflicts_with_all = ["in_place", "dry_run"])]
+    stream: bool,
+    /// List every replacement made, with its line and column.
+    #[arg(long, conflicts_with = "stream")]
+    stats: bool,
+    /// Print what was replaced where as JSON on standard output, instead of
+    /// a summary or diffs.
+    #[arg(long, conflicts_with = "stream")]
+    json: bool,
+    /// After editing, keep watching the paths, and edit files again whenever
+    /// they change, until interrupted. Only the first edit can be undone;
+    /// those made while watching aren't journaled.
+    #[arg(
+        long,
+        requires = "in_place",
+        conflicts_with_all = ["dry_run", "interactive", "json"]
+    )]
+    watch: bool,
+    /// Edit up to N files at once. Defaults to the number of CPUs.
+    #[arg(short = 'j', long, value_name = "N", conflicts_with = "interactive")]
+    jobs: Option<NonZeroUsize>,
+    /// Replace at most N matches in each file.
+    #[arg(long, value_name = "N")]
+    count: Option<NonZeroUsize>,
+    /// Keep a copy of each edited file, named with SUFFIX appended.
+    #[arg(
+        long,
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = ".bak",
+        value_parser = clap::builder::NonEmptyStringValueParser::new(),
+        requires = "in_place"
+    )]
+    backup: Option<String>,
+    /// Only edit files matching GLOB. May be repeated.
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+    /// Don't edit files matching GLOB. May be repeated.
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+}
+
+/// Run quickreplace with the process's arguments, and return the status to
+/// exit with.
+pub fn main() -> i32 {
+    let args = match parse_args_from(std::env::args_os()) {
+        Ok(args) => args,
+        Err(e) => {
+            let _ = e.print();
+            return if e.use_stderr() { 1 } else { 0 };
+        }
+    };
+    match run(&args) {
+        Ok(status) => status,
+        Err(e) => fail(&e),
+    }
+}
+
+/// Report `error` and return the status to exit with.
+fn fail(error: &CliError) -> i32 {
+    eprintln!("{} {}", "Error:".red().bold(), error);
+    error.exit_code()
+}
+
+/// Do what `args` asks, and return the exit status.
+fn run(args: &Arguments) -> Result<i32, CliError> {
+    if let Some(run_id) = &args.undo {
+        let (run_id, restored) = journal::undo(&args.journal_dir, run_id.as_deref())?;
+        println!(
+            "{} restored {} files changed by run {}",
+            "Undone:".green().bold(),
+            restored,
+            run_id
+        );
+        return Ok(0);
+    }
+
+    let rules = load_rules(args)?;
+    let mut prompter = args.interactive.then(|| {
+        interactive::Prompter::new(io::stdin().lock(), io::stderr(), io::stderr().is_terminal())
+    });
+
+    if args.in_place || args.dry_run {
+        let status = edit_files(args, &rules, &mut prompter)?;
+        if args.watch {
+            return watch::watch(args, &rules);
+        }
+        return Ok(status);
+    }
+
+    if args.stream {
+        // There are no rules files in stream mode, so this is the one rule
+        // from the command line.
+        let Matcher::Bytes(regex) = &rules[0].matcher else {
+            unreachable!("--stream always matches bytes");
+        };
+        stream_file(args, regex, &rules[0].replacement)?;
+        return Ok(0);
+    }
+
+    let (filename, output) = (&args.paths[0], args.output.as_ref().unwrap());
+    let data = read_input(filename).map_err(|e| QuickReplaceError::read(filename, e))?;
+    let (format, text) =
+        encoding::decode_file(Path::new(filename), &data, rules::decoding(&rules))?;
+    let (replaced_text, edits) = apply(args, &rules, &mut prompter, Path::new(filename), &text)?;
+    let replaced_data = encoding::encode(format, &replaced_text);
+    write_output(output, &replaced_data).map_err(|e| QuickReplaceError::write(output, e))?;
+
+    let mut report = Report::new(rules.iter().map(|rule| rule.pattern.clone()));
+    report.files_searched = 1;
+    if !edits.is_empty() {
+        report.add_file(filename, edits);
+    }
+    // Standard output may be the output file, so only JSON, which can't be
+    // combined with that, goes there.
+    if args.json {
+        println!("{}", report.to_json());
+    }
+    if args.rules.is_some() {
+        eprint!("{}", report.rule_hits());
+    }
+    if args.stats {
+        eprint!("{}", report.stats());
+    }
+    Ok(0)
+}
+
+/// Compile the rules given by `args`: those in the rules file, or else the
+/// single rule from the command line.
+fn load_rules(args: &Arguments) -> Result<Vec<Rule>, QuickReplaceError> {
+    let specs = match &args.rules {
+        Some(path) => {
+            let data = fs::read(path).map_err(|e| QuickReplaceError::read(path, e))?;
+            let (_, text) = encoding::decode_file(path, &data, Decoding::Lines)?;
+            let text = String::from_utf8(text)
+                .map_err(|_| QuickReplaceError::NotUtf8 { path: path.clone() })?;
+            let specs = rules::parse_rules(path, &text).map_err(|e| {
+                QuickReplaceError::BadArgs(format!(
+                    "invalid rules file '{}': {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            specs
+                .into_iter()
+                .map(|spec| RuleSpec {
+                    fixed_strings: spec.fixed_strings || args.fixed_strings,
+                    ignore_case: spec.ignore_case || args.ignore_case,
+                    word_regexp: spec.word_regexp || args.word_regexp,
+                    multiline: spec.multiline || args.multiline,
+                    preserve_case: spec.preserve_case || args.preserve_case,
+                    scope: spec.scope.or(args.scope),
+                    lines: spec.lines.or(args.lines),
+                    before: spec.before.or_else(|| args.before.clone()),
+                    after: spec.after.or_else(|| args.after.clone()),
+                    within: spec.within.or(args.within),
+                    ..spec
+                })
+                .collect()
+        }
+        None => vec![RuleSpec {
+            pattern: args.target.clone().unwrap(),
+            replacement: args.replacement.clone().unwrap(),
+            fixed_strings: args.fixed_strings,
+            ignore_case: args.ignore_case,
+            word_regexp: args.word_regexp,
+            multiline: args.multiline,
+            preserve_case: args.preserve_case,
+            scope: args.scope,
+            lines: args.lines,
+            before: args.before.clone(),
+            after: args.after.clone(),
+            within: args.within,
+            ..RuleSpec::default()
+        }],
+    };
+
+    specs
+        .iter()
+        .map(|spec| Rule::new(spec, args.bytes || args.stream))
+        .collect()
+}
+
+/// Read all of `filename`, or of standard input if it is `-`.
+fn read_input(filename: &str) -> io::Result<Vec<u8>> {
+    if filename == "-" {
+        let mut data = vec![];
+        io::stdin().read_to_end(&mut data)?;
+        Ok(data)
+    } else {
+        fs::read(filename)
+    }
+}
+
+/// Write `data` to `filename`, or to standard output if it is `-`.
+fn write_output(filename: &str, data: &[u8]) -> io::Result<()> {
+    if filename == "-" {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(data)?;
+        stdout.flush()
+    } else {
+        fs::write(filename, data)
+    }
+}
+
+/// Copy the input file to the output file a line at a time, replacing as we
+/// go.
+fn stream_file(
+    args: &Arguments,
+    regex: &regex::bytes::Regex,
+    replacement: &Replacement,
+) -> Result<(), QuickReplaceError> {
+    let (filename, output) = (&args.paths[0], args.output.as_ref().unwrap());
+
+    let input: Box<dyn Read> = if filename == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(filename).map_err(|e| QuickReplaceError::read(filename, e))?)
+    };
+    let writer: Box<dyn Write> = if output == "-" {
+        Box::new(io::stdout().lock())
+    } else {
+        Box::new(File::create(output).map_err(|e| QuickReplaceError::write(output, e))?)
+    };
+
+    let result = stream::replace_lines(
+        regex,
+        replacement,
+        BufReader::new(input),
+        BufWriter::new(writer),
+        limit(args),
+    );
+    match result {
+        Ok(_) => Ok(()),
+        Err(stream::StreamError::Read(e)) => Err(QuickReplaceError::read(filename, e)),
+        Err(stream::StreamError::Write(e)) => Err(QuickReplaceError::write(output, e)),
+    }
+}
+
+/// Build the filter for `--include` and `--exclude`, which also skips the
+/// files `--backup` leaves.
+fn file_filter(args: &Arguments) -> Result<walk::Filter, QuickReplaceError> {
+    let filter = walk::Filter::new(&args.include, &args.exclude)
+        .map_err(|e| QuickReplaceError::BadArgs(format!("invalid glob: {}", e)))?;
+    Ok(filter.skip_backups(args.backup.as_deref()))
+}
+
+/// Apply the replacement to every file under `args.paths`, as `edit_paths`
+/// does.
+fn edit_files(
+    args: &Arguments,
+    rules: &[Rule],
+    prompter: &mut Option<StdPrompter>,
+) -> Result<i32, CliError> {
+    let files = walk::find_files(&args.paths, &file_filter(args)?);
+    edit_paths(args, rules, prompter, files, true)
+}
+
+/// Apply the replacement to each of `files`, and print a summary. Files that
+/// change are rewritten in place or, for a dry run, shown as a unified diff
+/// on standard output.
+///
+/// Files are edited in parallel, unless we're asking the user about each
+/// match, but reported in a stable order. A file that can't be read or
+/// written is reported, but doesn't stop the others from being edited.
+/// If `journaled`, save what we changed, for `--undo`.
+/// Return the exit status: that of the last failure if there was one, else 5
+/// if a dry run found changes to make, else zero.
+fn edit_paths(
+    args: &Arguments,
+    rules: &[Rule],
+    prompter: &mut Option<StdPrompter>,
+    files: Vec<Result<PathBuf, ignore::Error>>,
+    journaled: bool,
+) -> Result<i32, CliError> {
+    let outcomes: Vec<Outcome> = if prompter.is_some() {
+        let mut outcomes = vec![];
+        for path in files {
+            if prompter.as_ref().is_some_and(|p| p.has_quit()) {
+                break;
+            }
+            outcomes.push(edit_file(args, rules, prompter, path));
+        }
+        outcomes
+    } else {
+        // Zero threads means one per CPU.
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(args.jobs.map_or(0, NonZeroUsize::get))
+            .build()
+            .map_err(|e| QuickReplaceError::BadArgs(format!("can't start threads: {}", e)))?;
+        pool.install(|| {
+            files
+                .into_par_iter()
+                .map(|path| edit_file(args, rules, &mut None, path))
+                .collect()
+        })
+    };
+
+    let mut status = 0;
+    let mut report = Report::new(rules.iter().map(|rule| rule.pattern.clone()));
+    let mut journal = vec![];
+    for outcome in outcomes {
+        match outcome {
+            Outcome::Binary => {}
+            Outcome::Unchanged => report.files_searched += 1,
+            Outcome::Changed {
+                path,
+                edits,
+                diff,
+                entry,
+            } => {
+                report.files_searched += 1;
+                report.add_file(&path.to_string_lossy(), edits);
+                if let Some(diff) = diff {
+                    print!("{}", diff);
+                }
+                journal.extend(entry);
+            }
+            Outcome::Failed(e) => status = fail(&e),
+        }
+    }
+    if journaled && !journal.is_empty() {
+        match journal::save(&args.journal_dir, journal) {
+            Ok(run_id) => report.run_id = Some(run_id),
+            Err(e) => status = fail(&QuickReplaceError::write(&args.journal_dir, e).into()),
+        }
+    }
+
+    if prompter.as_ref().is_some_and(|p| p.has_quit()) {
+        eprintln!("Stopped at the user's request.");
+    }
+    if args.dry_run && status == 0 && report.files_changed > 0 {
+        status = 5;
+    }
+
+    if args.json {
+        println!("{}", report.to_json());
+        return Ok(status);
+    }
+    let mut summary = format!(
+        "{} {} replacements in {} of {} files",
+        if args.dry_run { "Dry run:" } else { "Done:" }
+            .green()
+            .bold(),
+        report.replacements,
+        report.files_changed,
+        report.files_searched
+    );
+    if args.dry_run {
+        summary.push_str(" would be made");
+    }
+    summary.push('\n');
+    if let Some(run_id) = &report.run_id {
+        summary.push_str(&format!(
+            "To revert this run: quickreplace --undo {}\n",
+            run_id
+        ));
+    }
+    if args.rules.is_some() {
+        summary.push_str(&report.rule_hits());
+    }
+    if args.stats {
+        summary.push_str(&report.stats());
+    }
+    if args.dry_run {
+        // Keep standard output a clean patch.
+        eprint!("{}", summary);
+    } else {
+        print!("{}", summary);
+    }
+    Ok(status)
+}
+
+/// What became of one file in `edit_files`.
+enum Outcome {
+    /// The file looked binary, so wasn't searched.
+    Binary,
+    Unchanged,
+    /// The file was changed or, for a dry run, would be. `diff` is the diff
+    /// to show for a dry run, and `entry` the journal entry for undoing the
+    /// change otherwise.
+    Changed {
+        path: PathBuf,
+        edits: Vec<Edit>,
+        diff: Option<String>,
+        entry: Option<journal::FileEntry>,
+    },
+    Failed(CliError),
+}
+
+/// Apply `rules` to the file at `path`, one of those found by `edit_files`,
+/// and write the result back unless this is a dry run.
+fn edit_file(
+    args: &Arguments,
+    rules: &[Rule],
+    prompter: &mut Option<StdPrompter>,
+    path: Result<PathBuf, ignore::Error>,
+) -> Outcome {
+    let path = match path {
+        Ok(v) => v,
+        Err(e) => return Outcome::Failed(e.into()),
+    };
+
+    let data = match fs::read(&path) {
+        Ok(v) => v,
+        Err(e) => return Outcome::Failed(QuickReplaceError::read(&path, e).into()),
+    };
+    let (format, text) = match encoding::decode_file(&path, &data, rules::decoding(rules)) {
+        Ok(v) => v,
+        Err(_) if walk::is_binary(&data) => return Outcome::Binary,
+        Err(e) => return Outcome::Failed(e.into()),
+    };
+    if walk::is_binary(&text) {
+        return Outcome::Binary;
+    }
+    let (replaced_text, edits) = match apply(args, rules, prompter, &path, &text) {
+        Ok(v) => v,
+        Err(e) => return Outcome::Failed(e.into()),
+    };
+    if replaced_text == text {
+        return Outcome::Unchanged;
+    }
+
+    if args.dry_run {
+        let diff = (!args.json).then(|| {
+            let color = std::io::stdout().is_terminal();
+            let (old, new) = (
+                String::from_utf8_lossy(&text),
+                String::from_utf8_lossy(&replaced_text),
+            );
+            diff::unified_diff(&path.to_string_lossy(), &old, &new, color)
+        });
+        return Outcome::Changed {
+            path,
+            edits,
+            diff,
+            entry: None,
+        };
+    }
+
+    let replaced_data = encoding::encode(format, &replaced_text);
+    let absolute = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+    let entry = journal::FileEntry::new(absolute, &data, &replaced_data);
+    match in_place::write_atomically(&path, &replaced_data, args.backup.as_deref()) {
+        Ok(_) => Outcome::Changed {
+            path,
+            edits,
+            diff: None,
+            entry: Some(entry),
+        },
+        Err(e) => Outcome::Failed(QuickReplaceError::write(&path, e).into()),
+    }
+}
+
+type StdPrompter = interactive::Prompter<io::StdinLock<'static>, io::Stderr>;
+
+/// Apply `rules` to `data`, the contents of `path`, as `rules::apply_rules`
+/// does, but asking about each match first if there's a `prompter`.
+fn apply(
+    args: &Arguments,
+    rules: &[Rule],
+    prompter: &mut Option<StdPrompter>,
+    path: &Path,
+    data: &[u8],
+) -> Result<(Vec<u8>, Vec<Edit>), QuickReplaceError> {
+    let not_utf8 = |_| QuickReplaceError::NotUtf8 {
+        path: path.to_path_buf(),
+    };
+    let Some(prompter) = prompter else {
+        return rules::apply_rules(rules, path, data, limit(args)).map_err(not_utf8);
+    };
+
+    // There are no rules files in interactive mode, so this is the one rule
+    // from the command line.
+    let Matcher::Text(regex) = &rules[0].matcher else {
+        unreachable!("--interactive always matches text");
+    };
+    let text = std::str::from_utf8(data).map_err(not_utf8)?;
+    let (replaced, edits) = prompter.replace(
+        path,
+        regex,
+        &rules[0].replacement,
+        text,
+        limit(args),
+        rules[0].accepts_match(path, data),
+    );
+    Ok((replaced.into_bytes(), edits))
+}
+
+fn parse_args_from<I, T>(raw: I) -> Result<Arguments, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let mut args = Arguments::try_parse_from(raw)?;
+    if args.undo.is_some() {
+        return Ok(args);
+    }
+    let invalid = |message: &str| Arguments::command().error(ErrorKind::ArgumentConflict, message);
+
+    // With a rules file, every positional argument is a path.
+    if args.rules.is_some() {
+        let mut paths: Vec<String> = args.target.take().into_iter().collect();
+        paths.extend(args.replacement.take());
+        paths.append(&mut args.paths);
+        args.paths = paths;
+    } else if args.replacement.is_none() {
+        return Err(Arguments::command().error(
+            ErrorKind::MissingRequiredArgument,
+            "a target and a replacement are required unless --rules is given",
+        ));
+    }
+    if args.paths.is_empty() {
+        return Err(Arguments::command().error(
+            ErrorKind::MissingRequiredArgument,
+            "at least one path is required",
+        ));
+    }
+
+    // In place or for a dry run, any number of paths may follow; otherwise
+    // exactly one input and one output.
+    if args.in_place || args.dry_run {
+        if args.paths.iter().any(|path| path == "-") {
+            return Err(invalid(
+                "standard input can't be used with --in-place or --dry-run",
+            ));
+        }
+    } else {
+        if args.paths.len() != 2 {
+            return Err(Arguments::command().error(
+                ErrorKind::WrongNumberOfValues,
+                format!(
+                    "expected an input and an output path, got {} paths",
+                    args.paths.len()
+                ),
+            ));
+        }
+        if args.json && args.paths[1] == "-" {
+            return Err(invalid(
+                "standard output is needed for the output file, so --json can't be used",
+            ));
+        }
+        if args.interactive && args.paths[0] == "-" {
+            return Err(invalid(
+                "standard input is needed for answers with --interactive",
+            ));
+        }
+        if !args.include.is_empty() || !args.exclude.is_empty() {
+            return Err(invalid(
+                "--include and --exclude can only be used with --in-place or --dry-run",
+            ));
+        }
+        args.output = args.paths.pop();
+    }
+    Ok(args)
+}
+
+/// The most replacements to make in each file, where zero means no limit.
+fn limit(args: &Arguments) -> usize {
+    args.count.map_or(0, NonZeroUsize::get)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn parse(args: &[&str]) -> Result<Arguments, clap::Error> {
+        parse_args_from(std::iter::once("quickreplace").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn test_parse_args_positional() {
+        let args = parse(&["world", "there", "in.txt", "out.txt"]).unwrap();
+        assert_eq!(args.target.as_deref(), Some("world"));
+        assert_eq!(args.replacement.as_deref(), Some("there"));
+        assert_eq!(args.paths, ["in.txt"]);
+        assert_eq!(args.output.as_deref(), Some("out.txt"));
+
+        let args = parse(&["world", "there", "-", "-"]).unwrap();
+        assert_eq!(args.paths, ["-"]);
+        assert_eq!(args.output.as_deref(), Some("-"));
+
+        assert!(parse(&["world", "there", "in.txt"]).is_err());
+        assert!(parse(&["world", "there", "a.txt", "b.txt", "c.txt"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_flags() {
+        let args = parse(&["-iwm", "--count", "2", "a", "b", "in.txt", "out.txt"]).unwrap();
+        assert!(args.ignore_case && args.word_regexp && args.multiline);
+        assert_eq!(limit(&args), 2);
+        assert!(parse(&["--count", "0", "a", "b", "in.txt", "out.txt"]).is_err());
+
+        let args = parse(&["--in-place", "--backup", "a", "b", "src", "docs"]).unwrap();
+        assert_eq!(args.paths, ["src", "docs"]);
+        assert_eq!(args.output, None);
+        assert_eq!(args.backup.as_deref(), Some(".bak"));
+
+        let args = parse(&["--in-place", "--backup=.orig", "a", "b", "src"]).unwrap();
+        assert_eq!(args.backup.as_deref(), Some(".orig"));
+        // A backup named like the file itself would overwrite it.
+        assert!(parse(&["--in-place", "--backup=", "a", "b", "src"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_conflicts() {
+        assert!(parse(&["--backup", "a", "b", "in.txt", "out.txt"]).is_err());
+        assert!(parse(&["--include", "*.rs", "a", "b", "in.txt", "out.txt"]).is_err());
+        assert!(parse(&["--dry-run", "--include", "*.rs", "a", "b", "src"]).is_ok());
+        assert!(parse(&["--in-place", "a", "b", "-"]).is_err());
+        assert!(parse(&["--watch", "--in-place", "a", "b", "src"]).is_ok());
+        assert!(parse(&["--watch", "--dry-run", "a", "b", "src"]).is_err());
+
+        let e = parse(&["--help"]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::DisplayHelp);
+        assert!(!e.use_stderr());
+    }
+
+    #[test]
+    fn test_load_rules() {
+        let run = |flags: &[&str], text: &[u8]| {
+            let args = parse(flags).unwrap();
+            let rules = load_rules(&args).unwrap();
+            rules::apply_rules(&rules, Path::new(&args.paths[0]), text, limit(&args))
+                .map(|(data, edits)| (data, edits.len()))
+        };
+
+        assert_eq!(
+            run(
+                &["-i", "-w", "cat", "dog", "i", "o"],
+                b"Cat concatenate CAT"
+            ),
+            Ok((b"dog concatenate dog".to_vec(), 2))
+        );
+        assert_eq!(
+            run(&["-m", "^x", "y", "i", "o"], b"x\nx\n"),
+            Ok((b"y\ny\n".to_vec(), 2))
+        );
+        assert_eq!(
+            run(&["-F", "-w", "a.b", "$0", "i", "o"], b"a.b axb a.bc"),
+            Ok((b"$0 axb a.bc".to_vec(), 1))
+        );
+
+        assert_eq!(
+            run(
+                &["--preserve-case", "user_id", "account_id", "i", "o"],
+                b"userId = USER_ID"
+            ),
+            Ok((b"accountId = ACCOUNT_ID".to_vec(), 2))
+        );
+        assert!(parse(&["--preserve-case", "-i", "a", "b", "i", "o"]).is_err());
+        assert_eq!(
+            run(
+                &["--scope", "comments", "x", "y", "i.py", "o"],
+                b"x = 1  # x"
+            ),
+            Ok((b"x = 1  # y".to_vec(), 1))
+        );
+        assert!(parse(&["--scope", "docs", "x", "y", "i.py", "o"]).is_err());
+
+        assert_eq!(
+            run(&["-m", "a.b", "c", "i", "o"], b"a\nb"),
+            Ok((b"c".to_vec(), 1))
+        );
+        assert_eq!(
+            run(&["--lines", "2:", "x", "y", "i", "o"], b"x\nx\n"),
+            Ok((b"x\ny\n".to_vec(), 1))
+        );
+        assert_eq!(
+            run(
+                &["--before", "start", "--within", "0", "x", "y", "i", "o"],
+                b"x start x\nx\n"
+            ),
+            Ok((b"x start y\nx\n".to_vec(), 1))
+        );
+        assert!(parse(&["--lines", "0:3", "x", "y", "i", "o"]).is_err());
+        assert!(parse(&["--stream", "--after", "z", "x", "y", "-", "-"]).is_err());
+
+        // Only byte matching accepts invalid UTF-8.
+        assert!(run(&["x", "y", "i", "o"], b"x\xff").is_err());
+        assert_eq!(
+            run(&["--bytes", "x", "y", "i", "o"], b"x\xff"),
+            Ok((b"y\xff".to_vec(), 1))
+        );
+    }
+
+    #[test]
+    fn test_load_rules_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rules.txt");
+        fs::write(&path, "cat => dog\nDOG => wolf\n").unwrap();
+
+        let rules_flag = format!("--rules={}", path.display());
+        let args = parse(&[&rules_flag, "-i", "--in-place", "src", "docs"]).unwrap();
+        assert_eq!(args.target, None);
+        assert_eq!(args.paths, ["src", "docs"]);
+
+        // The -i applies to both rules.
+        let rules = load_rules(&args).unwrap();
+        let (replaced, edits) = rules::apply_rules(&rules, Path::new("a.txt"), b"Cat", 0).unwrap();
+        assert_eq!(replaced, b"wolf");
+        let rules_hit: Vec<usize> = edits.iter().map(|edit| edit.rule).collect();
+        assert_eq!(rules_hit, [0, 1]);
+
+        let args = parse(&[&rules_flag, "in.txt", "out.txt"]).unwrap();
+        assert_eq!(args.paths, ["in.txt"]);
+        assert_eq!(args.output.as_deref(), Some("out.txt"));
+
+        assert!(parse(&[&rules_flag, "--in-place"]).is_err());
+        assert!(parse(&[&rules_flag, "--stream", "-", "-"]).is_err());
+        assert!(parse(&["cat", "--in-place"]).is_err());
+    }
+
+    #[test]
+    fn test_load_rules_errors() {
+        let args = parse(&["(", "x", "in.txt", "out.txt"]).unwrap();
+        assert_eq!(load_rules(&args).err().unwrap().exit_code(), 4);
+
+        let args = parse(&["--rules", "/nonexistent/rules.txt", "in.txt", "out.txt"]).unwrap();
+        assert_eq!(load_rules(&args).err().unwrap().exit_code(), 2);
+    }
+
+    #[test]
+    fn test_parse_args_interactive() {
+        assert!(parse(&["--interactive", "--in-place", "a", "b", "src"]).is_ok());
+        assert!(parse(&["--interactive", "a", "b", "in.txt", "-"]).is_ok());
+        assert!(parse(&["--interactive", "a", "b", "-", "out.txt"]).is_err());
+        assert!(parse(&["--interactive", "--dry-run", "a", "b", "src"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_json() {
+        assert!(parse(&["--json", "a", "b", "in.txt", "out.txt"]).is_ok());
+        assert!(parse(&["--json", "a", "b", "in.txt", "-"]).is_err());
+        // The output path is missing, not standard output.
+        let error = parse(&["--json", "a", "b", "in.txt"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::WrongNumberOfValues);
+        assert!(parse(&["--json", "--stream", "a", "b", "in.txt", "out.txt"]).is_err());
+    }
+
+    #[test]
+    fn test_edit_files_parallel() {
+        let dir = tempfile::tempdir().unwrap();
+        for i in 0..20 {
+            fs::write(dir.path().join(format!("{:02}.txt", i)), "cat\n").unwrap();
+        }
+        fs::write(dir.path().join("10.txt"), "dog\n").unwrap();
+
+        let root = dir.path().to_str().unwrap();
+        let mut args = parse(&["--jobs", "4", "--in-place", "cat", "cow", root]).unwrap();
+        args.journal_dir = dir.path().join(journal::DIR);
+        let rules = load_rules(&args).unwrap();
+        assert_eq!(edit_files(&args, &rules, &mut None).unwrap(), 0);
+
+        let read = |i: usize| fs::read_to_string(dir.path().join(format!("{:02}.txt", i))).unwrap();
+        for i in 0..20 {
+            assert_eq!(read(i), if i == 10 { "dog\n" } else { "cow\n" });
+        }
+
+        // The run can be undone.
+        let mut undo = parse(&["--undo"]).unwrap();
+        undo.journal_dir = args.journal_dir.clone();
+        assert_eq!(run(&undo).unwrap(), 0);
+        for i in 0..20 {
+            assert_eq!(read(i), if i == 10 { "dog\n" } else { "cat\n" });
+        }
+        assert!(parse(&["-j", "0", "--in-place", "a", "b", "src"]).is_err());
+        assert!(parse(&["-j", "2", "--interactive", "--in-place", "a", "b", "src"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_args_undo() {
+        let args = parse(&["--undo", "1700000000000"]).unwrap();
+        assert_eq!(args.undo, Some(Some("1700000000000".to_string())));
+        assert_eq!(parse(&["--undo"]).unwrap().undo, Some(None));
+        assert!(parse(&["--undo", "--in-place"]).is_err());
+        assert!(parse(&["--undo", "1", "a", "b", "src"]).is_err());
+
+        // undo is a target like any other.
+        let args = parse(&["undo", "redo", "in.txt", "out.txt"]).unwrap();
+        assert!(args.undo.is_none());
+        assert_eq!(args.target.as_deref(), Some("undo"));
+    }
+
+    #[test]
+    fn test_parse_args_stream() {
+        assert!(parse(&["--stream", "a", "b", "-", "-"]).is_ok());
+        assert!(parse(&["--stream", "--scope", "code", "a", "b", "-", "-"]).is_err());
+        assert!(parse(&["--stream", "--in-place", "a", "b", "src"]).is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use similar::{ChangeTag, TextDiff};
+use std::fmt::Write;
+use text_colorizer::*;
+
+/// Number of unchanged lines shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+type Style = fn(&str) -> ColoredString;
+
+/// Return a unified diff from `old` to `new`, the contents of the file at
+/// `path` before and after replacing, in the format `patch` accepts.
+///
+/// With `color`, removed lines are red and added lines green, and within
+/// them the text that actually changed is highlighted.
+pub fn unified_diff(path: &str, old: &str, new: &str, color: bool) -> String {
+    let styled = |style: Style| if color { Some(style) } else { None };
+    let diff = TextDiff::from_lines(old, new);
+    let mut out = String::new();
+
+    paint(
+        &mut out,
+        &format!("--- {}\n+++ {}\n", path, path),
+        styled(|s| s.bold()),
+    );
+
+    for hunk in diff
+        .unified_diff()
+        .context_radius(CONTEXT_LINES)
+        .iter_hunks()
+    {
+        paint(
+            &mut out,
+            &format!("{}\n", hunk.header()),
+            styled(|s| s.cyan()),
+        );
+
+        for op in hunk.ops() {
+            for change in diff.iter_inline_changes(op) {
+                let (sign, plain, emphasized) = match change.tag() {
+                    ChangeTag::Delete => (
+                        "-",
+                        styled(|s| s.red()),
+                        styled(|s| s.red().bold().underline()),
+                    ),
+                    ChangeTag::Insert => (
+                        "+",
+                        styled(|s| s.green()),
+                        styled(|s| s.green().bold().underline()),
+                    ),
+                    ChangeTag::Equal => (" ", None, None),
+                };
+
+                paint(&mut out, sign, plain);
+                for (is_emphasized, value) in change.iter_strings_lossy() {
+                    // Keep the line break outside the escape codes.
+                    let text = value.strip_suffix('\n').unwrap_or(&value);
+                    paint(
+                        &mut out,
+                        text,
+                        if is_emphasized { emphasized } else { plain },
+                    );
+                    if text.len() < value.len() {
+                        out.push('\n');
+                    }
+                }
+                if change.missing_newline() {
+                    out.push_str("\n\\ No newline at end of file\n");
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Append `text` to `out`, in the given style if there is one.
+fn paint(out: &mut String, text: &str, style: Option<Style>) {
+    match style {
+        Some(style) if !text.is_empty() => write!(out, "{}", style(text)).unwrap(),
+        _ => out.push_str(text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        let old = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n";
+        let new = "one\ntwo\nthree\nFOUR\nfive\nsix\nseven\neight\n";
+        assert_eq!(
+            unified_diff("numbers.txt", old, new, false),
+            "--- numbers.txt\n\
+             +++ numbers.txt\n\
+             @@ -1,7 +1,7 @@\n \
+             one\n \
+             two\n \
+             three\n\
+             -four\n\
+             +FOUR\n \
+             five\n \
+             six\n \
+             seven\n"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_missing_newline() {
+        assert_eq!(
+            unified_diff("f", "a", "b", false),
+            "--- f\n+++ f\n@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+b\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_color() {
+        let diff = unified_diff("f", "a b\n", "a c\n", true);
+        assert!(diff.contains(&"b".red().bold().underline().to_string()));
+        assert!(diff.contains(&"c".green().bold().underline().to_string()));
+        assert!(!diff.contains(&"a ".red().bold().underline().to_string()));
+    }
+}
@@ -0,0 +1,66 @@
+use crate::error::QuickReplaceError;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Everything that can go wrong in a quickreplace run: whatever can go wrong
+/// replacing text, and the failures only the command line runs into.
+///
+/// Each kind of failure has its own exit status, given by `exit_code`: those
+/// of `QuickReplaceError`, plus
+///
+/// - 2: a directory couldn't be walked or watched
+/// - 6: a run can't be undone because its files have changed since
+///
+/// Command-line syntax errors are reported by the argument parser, also with
+/// status 1. A dry run that finds changes to make exits with status 5.
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error(transparent)]
+    Replace(#[from] QuickReplaceError),
+
+    #[error("{0}")]
+    Walk(#[from] ignore::Error),
+
+    #[error("failed to watch for changes: {0}")]
+    Watch(#[from] notify::Error),
+
+    #[error(
+        "can't undo run {run_id}, as these files have changed since: {}",
+        paths.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", ")
+    )]
+    ChangedSince { run_id: String, paths: Vec<PathBuf> },
+}
+
+impl CliError {
+    /// The status quickreplace exits with after this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Replace(e) => e.exit_code(),
+            CliError::Walk(_) | CliError::Watch(_) => 2,
+            CliError::ChangedSince { .. } => 6,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let bad_args = CliError::from(QuickReplaceError::BadArgs("bad".into()));
+        assert_eq!(bad_args.to_string(), "bad");
+        assert_eq!(bad_args.exit_code(), 1);
+        let watch = CliError::from(notify::Error::generic("gone"));
+        assert_eq!(watch.exit_code(), 2);
+        let changed = CliError::ChangedSince {
+            run_id: "1".into(),
+            paths: vec!["a".into(), "b".into()],
+        };
+        assert_eq!(
+            changed.to_string(),
+            "can't undo run 1, as these files have changed since: a, b"
+        );
+        assert_eq!(changed.exit_code(), 6);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::matcher::replace_with;
+use crate::replacement::Replacement;
+use crate::report::Edit;
+use regex::{Match, Regex};
+use std::io::{BufRead, Write};
+use std::ops::Range;
+use std::path::Path;
+use text_colorizer::*;
+
+/// Number of unchanged lines shown before and after each match.
+const CONTEXT_LINES: usize = 2;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    /// Ask about every match.
+    Ask,
+    /// The user answered "all": accept every match from now on.
+    AcceptAll,
+    /// The user answered "quit": reject every match from now on.
+    Quit,
+}
+
+/// Asks the user whether to make each replacement, reading answers from
+/// `input` and writing matches and prompts to `output`.
+pub struct Prompter<R, W> {
+    input: R,
+    output: W,
+    color: bool,
+    state: State,
+}
+
+impl<R: BufRead, W: Write> Prompter<R, W> {
+    pub fn new(input: R, output: W, color: bool) -> Prompter<R, W> {
+        Prompter {
+            input,
+            output,
+            color,
+            state: State::Ask,
+        }
+    }
+
+    /// Return true once the user has asked to stop.
+    pub fn has_quit(&self) -> bool {
+        self.state == State::Quit
+    }
+
+    /// Like `replace`, but show each match in `text`, the contents of the
+    /// file at `path`, and only make the replacements the user accepts.
+    /// Matches whose byte range `accept` rejects aren't shown.
+    pub fn replace<F>(
+        &mut self,
+        path: &Path,
+        regex: &Regex,
+        replacement: &Replacement,
+        text: &str,
+        limit: usize,
+        accept: F,
+    ) -> (String, Vec<Edit>)
+    where
+        F: Fn(Range<usize>) -> bool,
+    {
+        replace_with(regex, replacement, text, limit, |caps, expanded| {
+            let found = caps.get(0).unwrap();
+            accept(found.range()) && self.confirm(path, text, found, expanded)
+        })
+    }
+
+    fn confirm(&mut self, path: &Path, text: &str, found: Match, expanded: &str) -> bool {
+        match self.state {
+            State::AcceptAll => return true,
+            State::Quit => return false,
+            State::Ask => {}
+        }
+
+        // The prompt is a courtesy: if it can't be shown, still read the
+        // answer.
+        let _ = write!(
+            self.output,
+            "{}",
+            describe(path, text, found, expanded, self.color)
+        );
+        loop {
+            let _ = write!(self.output, "Replace? [y]es, [n]o, [a]ll, [q]uit: ");
+            let _ = self.output.flush();
+
+            let mut answer = String::new();
+            match self.input.read_line(&mut answer) {
+                Ok(0) | Err(_) => {
+                    self.state = State::Quit;
+                    return false;
+                }
+                Ok(_) => {}
+            }
+            match answer.trim() {
+                "y" | "yes" => return true,
+                "n" | "no" => return false,
+                "a" | "all" => {
+                    self.state = State::AcceptAll;
+                    return true;
+                }
+                "q" | "quit" => {
+                    self.state = State::Quit;
+                    return false;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Show the match `found` in `text`, the contents of the file at `path`: its
+/// location, the lines around it, and the lines it's on before and after
+/// replacing it with `expanded`, like a diff.
+fn describe(path: &Path, text: &str, found: Match, expanded: &str, color: bool) -> String {
+    let start = text[..found.start()].rfind('\n').map_or(0, |i| i + 1);
+    let end = text[found.end()..]
+        .find('\n')
+        .map_or(text.len(), |i| found.end() + i);
+    let first_line = text[..start].matches('\n').count() + 1;
+    let (before, after) = (&text[start..found.start()], &text[found.end()..end]);
+
+    let (old, new) = if color {
+        (
+            found.as_str().red().bold().underline().to_string(),
+            expanded.green().bold().underline().to_string(),
+        )
+    } else {
+        (found.as_str().to_string(), expanded.to_string())
+    };
+
+    let location = format!("{}:{}", path.display(), first_line);
+    let mut out = if color {
+        format!("{}\n", location.bold())
+    } else {
+        format!("{}\n", location)
+    };
+
+    let leading: Vec<&str> = text[..start].lines().collect();
+    let first_context = leading.len().saturating_sub(CONTEXT_LINES);
+    for (i, line) in leading[first_context..].iter().enumerate() {
+        out.push_str(&format!("  {:>5} | {}\n", first_context + i + 1, line));
+    }
+
+    for (sign, changed) in [("-", old), ("+", new)] {
+        let lines = format!("{}{}{}", before, changed, after);
+        for (i, line) in lines.lines().enumerate() {
+            out.push_str(&format!("{} {:>5} | {}\n", sign, first_line + i, line));
+        }
+    }
+
+    let trailing = text[end..].strip_prefix('\n').unwrap_or("");
+    let last_line = first_line + text[start..end].matches('\n').count();
+    for (i, line) in trailing.lines().take(CONTEXT_LINES).enumerate() {
+        out.push_str(&format!("  {:>5} | {}\n", last_line + i + 1, line));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "one\ntwo\nthree cat\nfour\nfive\nsix\n";
+
+    #[test]
+    fn test_describe() {
+        let regex = Regex::new("cat").unwrap();
+        let found = regex.find(TEXT).unwrap();
+        assert_eq!(
+            describe(Path::new("pets.txt"), TEXT, found, "dog", false),
+            "pets.txt:3\n\
+             \x20     1 | one\n\
+             \x20     2 | two\n\
+             -     3 | three cat\n\
+             +     3 | three dog\n\
+             \x20     4 | four\n\
+             \x20     5 | five\n"
+        );
+    }
+
+    #[test]
+    fn test_describe_at_edges() {
+        let regex = Regex::new("a\nb").unwrap();
+        let text = "a\nb";
+        let found = regex.find(text).unwrap();
+        assert_eq!(
+            describe(Path::new("f"), text, found, "c", false),
+            "f:1\n-     1 | a\n-     2 | b\n+     1 | c\n"
+        );
+    }
+
+    fn run(answers: &str, text: &str, limit: usize) -> (String, usize, bool) {
+        let regex = Regex::new("cat").unwrap();
+        let mut prompts = vec![];
+        let mut prompter = Prompter::new(answers.as_bytes(), &mut prompts, false);
+        let (replaced, edits) = prompter.replace(
+            Path::new("f"),
+            &regex,
+            &"dog".parse().unwrap(),
+            text,
+            limit,
+            |_| true,
+        );
+        (replaced, edits.len(), prompter.has_quit())
+    }
+
+    #[test]
+    fn test_prompter() {
+        let text = "cat cat cat cat";
+        assert_eq!(
+            run("y\nn\nyes\nno\n", text, 0),
+            ("dog cat dog cat".to_string(), 2, false)
+        );
+        // Unrecognized answers are asked again.
+        assert_eq!(
+            run("maybe\nn\na\n", text, 0),
+            ("cat dog dog dog".to_string(), 3, false)
+        );
+        assert_eq!(
+            run("y\nq\n", text, 0),
+            ("dog cat cat cat".to_string(), 1, true)
+        );
+        // Running out of answers is like quitting.
+        assert_eq!(run("", text, 0), (text.to_string(), 0, true));
+        // The limit counts only accepted replacements.
+        assert_eq!(
+            run("n\ny\ny\n", text, 2),
+            ("cat dog dog cat".to_string(), 2, false)
+        );
+    }
+}
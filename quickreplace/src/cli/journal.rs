@@ -0,0 +1,292 @@
+use super::error::CliError;
+use crate::error::QuickReplaceError;
+use crate::in_place;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where `--in-place` runs record what they changed, relative to the current
+/// directory.
+pub const DIR: &str = ".quickreplace";
+
+/// What one `--in-place` run changed, saved as `<run_id>.json` in the
+/// journal directory so that `quickreplace --undo` can revert it.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    pub run_id: String,
+    pub files: Vec<FileEntry>,
+}
+
+/// One file changed by a run.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// The absolute path of the file.
+    pub path: PathBuf,
+    /// SHA-256 hashes of the file's contents before and after the run.
+    pub original_hash: String,
+    pub hash: String,
+    /// What to put back to turn the new contents into the original.
+    pub patch: Vec<Hunk>,
+}
+
+/// A range of lines in a file's new contents, as a byte offset and length,
+/// and the original bytes they replaced.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Hunk {
+    pub offset: usize,
+    pub len: usize,
+    pub original: Vec<u8>,
+}
+
+/// Return the SHA-256 hash of `data`, in hex.
+pub fn hash(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Split `data` into lines, each with its terminator, and return them along
+/// with the offset at which each starts and, last, the length of `data`.
+fn lines(data: &[u8]) -> (Vec<&[u8]>, Vec<usize>) {
+    let lines: Vec<&[u8]> = data.split_inclusive(|&byte| byte == b'\n').collect();
+    let mut offsets = vec![0];
+    for line in &lines {
+        offsets.push(offsets.last().unwrap() + line.len());
+    }
+    (lines, offsets)
+}
+
+impl FileEntry {
+    /// Describe changing the file at `path` from `original` to `edited`.
+    pub fn new(path: PathBuf, original: &[u8], edited: &[u8]) -> FileEntry {
+        let (old_lines, old_offsets) = lines(original);
+        let (new_lines, new_offsets) = lines(edited);
+        let patch = similar::capture_diff_slices(similar::Algorithm::Myers, &old_lines, &new_lines)
+            .iter()
+            .map(|op| op.as_tag_tuple())
+            .filter(|(tag, _, _)| *tag != similar::DiffTag::Equal)
+            .map(|(_, old, new)| Hunk {
+                offset: new_offsets[new.start],
+                len: new_offsets[new.end] - new_offsets[new.start],
+                original: original[old_offsets[old.start]..old_offsets[old.end]].to_vec(),
+            })
+            .collect();
+        FileEntry {
+            path,
+            original_hash: hash(original),
+            hash: hash(edited),
+            patch,
+        }
+    }
+
+    /// Return true if `data` is what the run left in the file.
+    pub fn is_unchanged(&self, data: &[u8]) -> bool {
+        hash(data) == self.hash
+    }
+
+    /// Turn `edited`, the contents the run left in the file, back into the
+    /// original contents.
+    pub fn revert(&self, edited: &[u8]) -> Vec<u8> {
+        let mut original = Vec::with_capacity(edited.len());
+        let mut last = 0;
+        for hunk in &self.patch {
+            original.extend_from_slice(&edited[last..hunk.offset]);
+            original.extend_from_slice(&hunk.original);
+            last = hunk.offset + hunk.len;
+        }
+        original.extend_from_slice(&edited[last..]);
+        original
+    }
+}
+
+/// Save a journal of `files` in `dir`, creating it if need be, under a new
+/// run ID, and return the ID.
+///
+/// Run IDs are the time in milliseconds, so that they sort in the order the
+/// runs were made.
+pub fn save(dir: &Path, files: Vec<FileEntry>) -> io::Result<String> {
+    fs::create_dir_all(dir)?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis());
+    // Two runs in the same millisecond get consecutive IDs.
+    for millis in now.. {
+        let run_id = format!("{:013}", millis);
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(dir.join(format!("{}.json", run_id)));
+        let mut file = match file {
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            result => result?,
+        };
+        let journal = Journal { run_id, files };
+        file.write_all(serde_json::to_string_pretty(&journal)?.as_bytes())?;
+        file.sync_all()?;
+        return Ok(journal.run_id);
+    }
+    unreachable!("ran out of run IDs")
+}
+
+/// Load the journal of the run `run_id` from `dir`, or of the latest run if
+/// `run_id` is `None`, and return it with the path it was loaded from.
+pub fn load(dir: &Path, run_id: Option<&str>) -> Result<(Journal, PathBuf), QuickReplaceError> {
+    let path = match run_id {
+        Some(run_id) => dir.join(format!("{}.json", run_id)),
+        None => {
+            let entries = match fs::read_dir(dir) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    return Err(QuickReplaceError::BadArgs(
+                        "there are no runs to undo".into(),
+                    ))
+                }
+                Err(e) => return Err(QuickReplaceError::read(dir, e)),
+            };
+            let mut journals = vec![];
+            for entry in entries {
+                let path = entry.map_err(|e| QuickReplaceError::read(dir, e))?.path();
+                if path.extension().is_some_and(|e| e == "json") {
+                    journals.push(path);
+                }
+            }
+            journals
+                .into_iter()
+                .max()
+                .ok_or_else(|| QuickReplaceError::BadArgs("there are no runs to undo".into()))?
+        }
+    };
+
+    let text = fs::read_to_string(&path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => {
+            QuickReplaceError::BadArgs(format!("no run '{}' to undo", run_id.unwrap_or_default()))
+        }
+        _ => QuickReplaceError::read(&path, e),
+    })?;
+    let journal = serde_json::from_str(&text).map_err(|e| {
+        QuickReplaceError::BadArgs(format!("invalid journal '{}': {}", path.display(), e))
+    })?;
+    Ok((journal, path))
+}
+
+/// Revert the run `run_id`, or the latest run, recorded in `dir`, and delete
+/// its journal so that the next undo reverts the run before it. Return the
+/// run reverted and the number of files restored.
+///
+/// If any of the files has changed since the run, nothing is reverted.
+pub fn undo(dir: &Path, run_id: Option<&str>) -> Result<(String, usize), CliError> {
+    let (journal, journal_path) = load(dir, run_id)?;
+
+    let mut contents = vec![];
+    let mut changed = vec![];
+    for file in &journal.files {
+        let data = fs::read(&file.path).map_err(|e| QuickReplaceError::read(&file.path, e))?;
+        if !file.is_unchanged(&data) {
+            changed.push(file.path.clone());
+        }
+        contents.push(data);
+    }
+    if !changed.is_empty() {
+        return Err(CliError::ChangedSince {
+            run_id: journal.run_id,
+            paths: changed,
+        });
+    }
+
+    for (file, data) in journal.files.iter().zip(contents) {
+        in_place::write_atomically(&file.path, &file.revert(&data), None)
+            .map_err(|e| QuickReplaceError::write(&file.path, e))?;
+    }
+    fs::remove_file(&journal_path).map_err(|e| QuickReplaceError::write(&journal_path, e))?;
+    Ok((journal.run_id, journal.files.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_revert() {
+        let cases: [(&[u8], &[u8]); 4] = [
+            (b"one\ntwo\nthree\n", b"one\n2\nthree\n"),
+            (b"a\nb\nc", b"a\nc"),
+            (b"", b"new\nlines\n"),
+            (b"x\xff\ny\n", b"y\n\xfex\n"),
+        ];
+        for (original, edited) in cases {
+            let entry = FileEntry::new(PathBuf::from("f"), original, edited);
+            assert!(entry.is_unchanged(edited));
+            assert!(!entry.is_unchanged(original));
+            assert_eq!(entry.revert(edited), original);
+            assert_eq!(entry.original_hash, hash(original));
+        }
+
+        // Only the changed lines are kept.
+        let entry = FileEntry::new(PathBuf::from("f"), b"a\nb\nc\n", b"a\nB\nc\n");
+        assert_eq!(
+            entry.patch,
+            [Hunk {
+                offset: 2,
+                len: 2,
+                original: b"b\n".to_vec()
+            }]
+        );
+    }
+
+    #[test]
+    fn test_undo() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal_dir = dir.path().join(DIR);
+        let (a, b) = (dir.path().join("a.txt"), dir.path().join("b.txt"));
+
+        // Two runs, the second editing a file the first did.
+        fs::write(&a, "cat\n").unwrap();
+        fs::write(&b, "cat cat\n").unwrap();
+        let first = save(
+            &journal_dir,
+            vec![
+                FileEntry::new(a.clone(), b"cat\n", b"dog\n"),
+                FileEntry::new(b.clone(), b"cat cat\n", b"dog dog\n"),
+            ],
+        )
+        .unwrap();
+        fs::write(&a, "dog\n").unwrap();
+        fs::write(&b, "dog dog\n").unwrap();
+        let second = save(
+            &journal_dir,
+            vec![FileEntry::new(a.clone(), b"dog\n", b"wolf\n")],
+        )
+        .unwrap();
+        fs::write(&a, "wolf\n").unwrap();
+        assert!(second > first);
+
+        // The first run can't be undone while the second's edit stands.
+        match undo(&journal_dir, Some(&first)) {
+            Err(CliError::ChangedSince { run_id, paths }) => {
+                assert_eq!((run_id, paths), (first.clone(), vec![a.clone()]));
+            }
+            other => panic!("expected ChangedSince, got {:?}", other),
+        }
+        assert_eq!(fs::read_to_string(&b).unwrap(), "dog dog\n");
+
+        // Undoing the latest run, then the one before it, restores all.
+        assert_eq!(undo(&journal_dir, None).unwrap(), (second, 1));
+        assert_eq!(fs::read_to_string(&a).unwrap(), "dog\n");
+        assert_eq!(undo(&journal_dir, None).unwrap(), (first, 2));
+        assert_eq!(fs::read_to_string(&a).unwrap(), "cat\n");
+        assert_eq!(fs::read_to_string(&b).unwrap(), "cat cat\n");
+
+        assert!(matches!(
+            undo(&journal_dir, None),
+            Err(CliError::Replace(QuickReplaceError::BadArgs(_)))
+        ));
+        assert!(matches!(
+            undo(&journal_dir, Some("123")),
+            Err(CliError::Replace(QuickReplaceError::BadArgs(_)))
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+use super::error::CliError;
+use super::journal;
+use super::{edit_paths, file_filter, Arguments};
+use crate::rules::Rule;
+use crate::walk;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// How long files must go unchanged before we edit them, so that a file
+/// being written in several steps is edited once, when it's done.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Watch `args.paths`, applying `rules` to the files under them whenever
+/// they change, until interrupted or the watch fails.
+///
+/// Our own edits change files too. To keep them from setting off another
+/// edit, and another, we remember what we left in each file, and ignore
+/// changes that leave it that way.
+///
+/// Edits made while watching aren't saved to the journal: there would be a
+/// run for every save in the editor, and `--undo` would only step back one
+/// at a time. The files' history is better kept by whatever changed them.
+pub fn watch(args: &Arguments, rules: &[Rule]) -> Result<i32, CliError> {
+    let filter = file_filter(args)?;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    for path in &args.paths {
+        watcher.watch(Path::new(path), RecursiveMode::Recursive)?;
+    }
+
+    eprintln!(
+        "Watching {} for changes; press Ctrl-C to stop.",
+        args.paths.join(", ")
+    );
+    let mut written = Written::default();
+    loop {
+        let changed = next_changes(&receiver, DEBOUNCE)?;
+        apply_changes(args, rules, &filter, &mut written, changed)?;
+    }
+}
+
+/// Wait for files to change, then for them to settle, and return the paths
+/// that changed: those named by events until none has arrived for `quiet`.
+fn next_changes(
+    receiver: &Receiver<notify::Result<Event>>,
+    quiet: Duration,
+) -> Result<BTreeSet<PathBuf>, CliError> {
+    let mut changed = BTreeSet::new();
+    let mut next = receiver.recv().map_err(|_| watch_stopped());
+    loop {
+        let event = next??;
+        if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            changed.extend(event.paths);
+        }
+        next = match receiver.recv_timeout(quiet) {
+            Ok(event) => Ok(event),
+            Err(_) if !changed.is_empty() => return Ok(changed),
+            Err(RecvTimeoutError::Timeout) => receiver.recv().map_err(|_| watch_stopped()),
+            Err(RecvTimeoutError::Disconnected) => Err(watch_stopped()),
+        };
+    }
+}
+
+fn watch_stopped() -> CliError {
+    CliError::Watch(notify::Error::generic("the watcher stopped"))
+}
+
+/// Edit those of the `changed` paths that `edit_files` would have: files
+/// that aren't hidden or ignored, and that `filter` accepts. Skip files
+/// we've already edited and that haven't changed since.
+fn apply_changes(
+    args: &Arguments,
+    rules: &[Rule],
+    filter: &walk::Filter,
+    written: &mut Written,
+    changed: BTreeSet<PathBuf>,
+) -> Result<i32, CliError> {
+    let files: Vec<PathBuf> = changed
+        .into_iter()
+        .filter(|path| {
+            path.is_file()
+                && !is_hidden(path, &args.paths)
+                && !args
+                    .paths
+                    .iter()
+                    .any(|root| walk::is_ignored(Path::new(root), path))
+                && filter.accepts(path)
+                && !written.is_unchanged(path)
+        })
+        .collect();
+    if files.is_empty() {
+        return Ok(0);
+    }
+
+    let status = edit_paths(
+        args,
+        rules,
+        &mut None,
+        files.iter().cloned().map(Ok).collect(),
+        false,
+    )?;
+    for path in files {
+        written.record(path);
+    }
+    Ok(status)
+}
+
+/// Return true if `path`, found under one of the watched `roots`, is hidden
+/// or in a hidden directory beneath it, as `walk::find_files` would skip.
+/// That includes our journal and the temporary files we write before
+/// renaming them into place.
+fn is_hidden(path: &Path, roots: &[String]) -> bool {
+    let relative = roots
+        .iter()
+        .find_map(|root| path.strip_prefix(root).ok())
+        .unwrap_or(Path::new(path.file_name().unwrap_or_default()));
+    relative
+        .components()
+        .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// What we last left in each file we've edited, by hash.
+#[derive(Default)]
+struct Written {
+    hashes: HashMap<PathBuf, String>,
+}
+
+impl Written {
+    /// Remember what's in the file at `path` now.
+    fn record(&mut self, path: PathBuf) {
+        if let Ok(data) = fs::read(&path) {
+            self.hashes.insert(path, journal::hash(&data));
+        }
+    }
+
+    /// Return true if the file at `path` holds what we last left in it.
+    fn is_unchanged(&self, path: &Path) -> bool {
+        match (self.hashes.get(path), fs::read(path)) {
+            (Some(hash), Ok(data)) => *hash == journal::hash(&data),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{load_rules, parse_args_from};
+    use notify::event::{AccessKind, CreateKind, ModifyKind};
+    use std::thread;
+
+    fn event(kind: EventKind, path: &str) -> notify::Result<Event> {
+        Ok(Event::new(kind).add_path(PathBuf::from(path)))
+    }
+
+    #[test]
+    fn test_next_changes() {
+        let (sender, receiver) = mpsc::channel();
+        let quiet = Duration::from_millis(50);
+
+        sender
+            .send(event(EventKind::Modify(ModifyKind::Any), "a"))
+            .unwrap();
+        sender
+            .send(event(EventKind::Access(AccessKind::Any), "b"))
+            .unwrap();
+        sender
+            .send(event(EventKind::Create(CreateKind::File), "c"))
+            .unwrap();
+        sender
+            .send(event(EventKind::Modify(ModifyKind::Any), "a"))
+            .unwrap();
+        let changed = next_changes(&receiver, quiet).unwrap();
+        assert_eq!(changed, BTreeSet::from(["a".into(), "c".into()]));
+
+        // Events that change nothing don't end the wait.
+        let later = thread::spawn(move || {
+            sender
+                .send(event(EventKind::Access(AccessKind::Any), "b"))
+                .unwrap();
+            thread::sleep(quiet * 2);
+            sender
+                .send(event(EventKind::Modify(ModifyKind::Any), "d"))
+                .unwrap();
+        });
+        let changed = next_changes(&receiver, quiet).unwrap();
+        assert_eq!(changed, BTreeSet::from(["d".into()]));
+        later.join().unwrap();
+
+        // Once the watcher is gone, there's nothing more to wait for.
+        assert!(next_changes(&receiver, quiet).is_err());
+    }
+
+    #[test]
+    fn test_is_hidden() {
+        let roots = ["src".to_string()];
+        assert!(!is_hidden(Path::new("src/a/b.rs"), &roots));
+        assert!(is_hidden(Path::new("src/.git/config"), &roots));
+        assert!(is_hidden(Path::new("src/a/.tmpX1yZ"), &roots));
+        let roots = [".config".to_string()];
+        assert!(!is_hidden(Path::new(".config/app.toml"), &roots));
+        assert!(is_hidden(Path::new("/elsewhere/.hidden"), &roots));
+    }
+
+    #[test]
+    fn test_apply_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let path = dir.path().join("gen.txt");
+
+        // This rule would go on rewriting its own output forever.
+        let mut args = parse_args_from(["quickreplace", "--in-place", "a", "aa", root]).unwrap();
+        args.journal_dir = dir.path().join(journal::DIR);
+        let rules = load_rules(&args).unwrap();
+        let filter = file_filter(&args).unwrap();
+        let mut written = Written::default();
+        let apply = |written: &mut Written| {
+            let changed = BTreeSet::from([path.clone(), args.journal_dir.clone()]);
+            apply_changes(&args, &rules, &filter, written, changed).unwrap();
+            fs::read_to_string(&path).unwrap()
+        };
+
+        fs::write(&path, "a").unwrap();
+        assert_eq!(apply(&mut written), "aa");
+        // The change we made is seen, but not acted on.
+        assert_eq!(apply(&mut written), "aa");
+        // Changes made by others are.
+        fs::write(&path, "a").unwrap();
+        assert_eq!(apply(&mut written), "aa");
+        // Edits made while watching can't be undone.
+        assert!(!args.journal_dir.exists());
+    }
+
+    #[test]
+    fn test_apply_changes_skips_backups() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        let path = dir.path().join("notes.txt");
+        let backup = dir.path().join("notes.txt.bak");
+
+        let args =
+            parse_args_from(["quickreplace", "--in-place", "--backup", "a", "b", root]).unwrap();
+        let rules = load_rules(&args).unwrap();
+        let filter = file_filter(&args).unwrap();
+        let mut written = Written::default();
+
+        fs::write(&path, "a").unwrap();
+        let changed = BTreeSet::from([path.clone()]);
+        apply_changes(&args, &rules, &filter, &mut written, changed).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "a");
+
+        // The backup it left is seen as a change, but isn't edited itself.
+        let changed = BTreeSet::from([backup.clone()]);
+        apply_changes(&args, &rules, &filter, &mut written, changed).unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "a");
+        assert!(!dir.path().join("notes.txt.bak.bak").exists());
+    }
+
+    #[test]
+    fn test_apply_changes_skips_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().to_str().unwrap();
+        fs::create_dir(dir.path().join("target")).unwrap();
+        fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+        let built = dir.path().join("target/out.txt");
+        let source = dir.path().join("in.txt");
+
+        let mut args = parse_args_from(["quickreplace", "--in-place", "a", "b", root]).unwrap();
+        args.journal_dir = dir.path().join(journal::DIR);
+        let rules = load_rules(&args).unwrap();
+        let filter = file_filter(&args).unwrap();
+        let mut written = Written::default();
+
+        fs::write(&built, "a").unwrap();
+        fs::write(&source, "a").unwrap();
+        let changed = BTreeSet::from([built.clone(), source.clone()]);
+        apply_changes(&args, &rules, &filter, &mut written, changed).unwrap();
+        assert_eq!(fs::read_to_string(&built).unwrap(), "a");
+        assert_eq!(fs::read_to_string(&source).unwrap(), "b");
+    }
+}
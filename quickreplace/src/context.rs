@@ -0,0 +1,216 @@
+use regex::bytes::Regex;
+use serde::Deserialize;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// How many lines around a match `--before` and `--after` look at, unless
+/// `--within` says otherwise.
+pub const DEFAULT_WITHIN: usize = 5;
+
+/// A range of lines, counting from 1, given as `START:END`. Either end may be
+/// left out, and both are included.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct LineRange {
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+impl FromStr for LineRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LineRange, String> {
+        let invalid = || format!("expected a line range like 10:200, got '{}'", s);
+        let (start, end) = s.split_once(':').ok_or_else(invalid)?;
+        let start = match start {
+            "" => 1,
+            start => start.parse().map_err(|_| invalid())?,
+        };
+        let end = match end {
+            "" => None,
+            end => Some(end.parse().map_err(|_| invalid())?),
+        };
+        if start == 0 || end.is_some_and(|end| end < start) {
+            return Err(invalid());
+        }
+        Ok(LineRange { start, end })
+    }
+}
+
+impl TryFrom<String> for LineRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<LineRange, String> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for LineRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.end {
+            Some(end) => write!(f, "{}:{}", self.start, end),
+            None => write!(f, "{}:", self.start),
+        }
+    }
+}
+
+/// Where in a file a rule's matches may be: within a range of lines, and
+/// near text matching other patterns.
+#[derive(Debug)]
+pub struct Context {
+    pub lines: Option<LineRange>,
+    /// A pattern that must match in the `within` lines before a match, or
+    /// earlier on its first line.
+    pub before: Option<Regex>,
+    /// A pattern that must match in the `within` lines after a match, or
+    /// later on its last line.
+    pub after: Option<Regex>,
+    pub within: usize,
+}
+
+impl Context {
+    /// Return true if there's nothing to check.
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_none() && self.before.is_none() && self.after.is_none()
+    }
+
+    /// Return true if a match at `range` of `data` is where it may be.
+    /// `lines` gives the offset at which each line of `data` starts, as
+    /// returned by `line_starts`.
+    pub fn accepts(&self, data: &[u8], lines: &[usize], range: Range<usize>) -> bool {
+        // Line numbers count from 1, so the number of lines starting at or
+        // before an offset is the number of the line it's on.
+        let line_of = |offset: usize| lines.partition_point(|&start| start <= offset);
+        let first = line_of(range.start);
+        let last = line_of(range.end.saturating_sub(1).max(range.start));
+
+        if let Some(allowed) = self.lines {
+            if first < allowed.start || allowed.end.is_some_and(|end| last > end) {
+                return false;
+            }
+        }
+        if let Some(before) = &self.before {
+            let from = lines[first.saturating_sub(self.within.saturating_add(1))];
+            if !before.is_match(&data[from..range.start]) {
+                return false;
+            }
+        }
+        if let Some(after) = &self.after {
+            let to = lines
+                .get(last.saturating_add(self.within))
+                .copied()
+                .unwrap_or(data.len());
+            if !after.is_match(&data[range.end..to.max(range.end)]) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Return the offset at which each line of `data` starts.
+pub fn line_starts(data: &[u8]) -> Vec<usize> {
+    let newlines = data
+        .iter()
+        .enumerate()
+        .filter(|(_, &byte)| byte == b'\n')
+        .map(|(i, _)| i + 1);
+    std::iter::once(0).chain(newlines).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_line_range() {
+        let range = |start, end| Ok(LineRange { start, end });
+        assert_eq!("10:200".parse(), range(10, Some(200)));
+        assert_eq!("10:".parse(), range(10, None));
+        assert_eq!(":200".parse(), range(1, Some(200)));
+        assert_eq!("5:5".parse(), range(5, Some(5)));
+        for bad in ["", "10", "0:5", "5:4", "a:b", "1:2:3"] {
+            assert!(bad.parse::<LineRange>().is_err(), "{}", bad);
+        }
+        assert_eq!("3:".parse::<LineRange>().unwrap().to_string(), "3:");
+    }
+
+    /// The line numbers of the matches of `pattern` in `text` that `context`
+    /// accepts.
+    fn accepted(context: &Context, text: &str, pattern: &str) -> Vec<usize> {
+        let lines = line_starts(text.as_bytes());
+        Regex::new(pattern)
+            .unwrap()
+            .find_iter(text.as_bytes())
+            .filter(|found| context.accepts(text.as_bytes(), &lines, found.range()))
+            .map(|found| lines.partition_point(|&start| start <= found.start()))
+            .collect()
+    }
+
+    fn context() -> Context {
+        Context {
+            lines: None,
+            before: None,
+            after: None,
+            within: DEFAULT_WITHIN,
+        }
+    }
+
+    #[test]
+    fn test_lines() {
+        let text = "x\nx\nx\nx\n";
+        let only = |lines: &str| Context {
+            lines: Some(lines.parse().unwrap()),
+            ..context()
+        };
+        assert_eq!(accepted(&only("2:3"), text, "x"), [2, 3]);
+        assert_eq!(accepted(&only("3:"), text, "x"), [3, 4]);
+        // A match must lie entirely within the range.
+        assert_eq!(accepted(&only("1:2"), text, r"x\nx\n"), [1]);
+        assert!(accepted(&only("2:2"), text, r"x\nx\n").is_empty());
+    }
+
+    #[test]
+    fn test_anchors() {
+        let text = "\
+struct Config {
+    port: 80,
+}
+struct Other {
+    port: 80,
+}
+";
+        let near = |before: Option<&str>, after: Option<&str>, within| Context {
+            before: before.map(|pattern| Regex::new(pattern).unwrap()),
+            after: after.map(|pattern| Regex::new(pattern).unwrap()),
+            within,
+            ..context()
+        };
+        assert_eq!(
+            accepted(&near(Some("struct Config"), None, 1), text, "port"),
+            [2]
+        );
+        assert_eq!(
+            accepted(&near(Some("struct Config"), None, 5), text, "port"),
+            [2, 5]
+        );
+        assert!(accepted(&near(Some("struct"), None, 0), text, "port").is_empty());
+        assert_eq!(
+            accepted(&near(Some("struct"), None, 0), text, "Config"),
+            [1]
+        );
+        assert_eq!(accepted(&near(None, Some("Other"), 2), text, "port"), [2]);
+        assert_eq!(
+            accepted(&near(Some("Config"), Some("Other"), 2), text, "port"),
+            [2]
+        );
+        // Anchors must be outside the match.
+        assert!(accepted(&near(None, Some("80"), 0), text, "80").is_empty());
+        // Any distance at all reaches the ends of the text.
+        assert_eq!(
+            accepted(&near(Some("struct"), Some("}"), usize::MAX), text, "port"),
+            [2, 5]
+        );
+    }
+}
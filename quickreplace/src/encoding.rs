@@ -0,0 +1,275 @@
+use crate::error::QuickReplaceError;
+use std::fmt;
+use std::path::Path;
+
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+const UTF16LE_BOM: &[u8] = b"\xFF\xFE";
+const UTF16BE_BOM: &[u8] = b"\xFE\xFF";
+
+/// How a file's text is encoded, as told by its byte order mark. Files with
+/// none are taken to be UTF-8. When matching bytes, files are taken as they
+/// are, byte order mark and all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+        })
+    }
+}
+
+impl Encoding {
+    fn detect(data: &[u8]) -> Encoding {
+        if data.starts_with(UTF8_BOM) {
+            Encoding::Utf8Bom
+        } else if data.starts_with(UTF16LE_BOM) {
+            Encoding::Utf16Le
+        } else if data.starts_with(UTF16BE_BOM) {
+            Encoding::Utf16Be
+        } else {
+            Encoding::Utf8
+        }
+    }
+}
+
+/// How a file stores its text: its encoding, and whether its lines end in
+/// CRLF.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Format {
+    pub encoding: Encoding,
+    pub crlf: bool,
+}
+
+/// How much of a file's format `decode` should take away.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decoding {
+    /// Take the file as it is, as when matching bytes.
+    Raw,
+    /// Remove any byte order mark and decode UTF-16, but leave line endings
+    /// alone, for rules that mention carriage returns.
+    Text,
+    /// As `Text`, and also turn CRLF line endings into LF.
+    Lines,
+}
+
+/// Split `data`, the contents of a file, into its format and its text, as
+/// UTF-8 with no byte order mark and, unless `decoding` says otherwise,
+/// lines ending in LF, for rules to match against. `encode` turns the text
+/// back into exactly `data`.
+///
+/// Lines are only taken to end in CRLF if every one of them does; files
+/// with mixed line endings are left as they are. Return the encoding if
+/// `data` isn't valid in it.
+pub fn decode(data: &[u8], decoding: Decoding) -> Result<(Format, Vec<u8>), Encoding> {
+    let encoding = match decoding {
+        Decoding::Raw => Encoding::Utf8,
+        Decoding::Text | Decoding::Lines => Encoding::detect(data),
+    };
+    let text = match encoding {
+        Encoding::Utf8 => data.to_vec(),
+        Encoding::Utf8Bom => data[UTF8_BOM.len()..].to_vec(),
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let units = &data[2..];
+            if !units.len().is_multiple_of(2) {
+                return Err(encoding);
+            }
+            let units: Vec<u16> = units
+                .chunks_exact(2)
+                .map(|pair| match encoding {
+                    Encoding::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                    _ => u16::from_be_bytes([pair[0], pair[1]]),
+                })
+                .collect();
+            String::from_utf16(&units)
+                .map_err(|_| encoding)?
+                .into_bytes()
+        }
+    };
+
+    let crlf = decoding == Decoding::Lines && uses_crlf(&text);
+    let text = if crlf {
+        let mut lf = Vec::with_capacity(text.len());
+        let mut rest = text.as_slice();
+        while let Some(i) = rest.windows(2).position(|pair| pair == b"\r\n") {
+            lf.extend_from_slice(&rest[..i]);
+            lf.push(b'\n');
+            rest = &rest[i + 2..];
+        }
+        lf.extend_from_slice(rest);
+        lf
+    } else {
+        text
+    };
+    Ok((Format { encoding, crlf }, text))
+}
+
+/// Like `decode`, for the contents of the file at `path`.
+pub fn decode_file(
+    path: &Path,
+    data: &[u8],
+    decoding: Decoding,
+) -> Result<(Format, Vec<u8>), QuickReplaceError> {
+    decode(data, decoding).map_err(|encoding| QuickReplaceError::BadEncoding {
+        path: path.to_path_buf(),
+        encoding,
+    })
+}
+
+/// Return true if `text` has lines, and every one of them ends in CRLF.
+fn uses_crlf(text: &[u8]) -> bool {
+    let mut lines = 0;
+    for (i, &byte) in text.iter().enumerate() {
+        if byte == b'\n' {
+            if i == 0 || text[i - 1] != b'\r' {
+                return false;
+            }
+            lines += 1;
+        }
+    }
+    lines > 0
+}
+
+/// Turn `text`, as returned by `decode`, back into the contents of a file in
+/// `format`. Text that can't be represented in UTF-16 because it isn't valid
+/// UTF-8 is written with U+FFFD in place of the invalid bytes.
+pub fn encode(format: Format, text: &[u8]) -> Vec<u8> {
+    let crlf;
+    let text = if format.crlf {
+        crlf = text
+            .split(|&byte| byte == b'\n')
+            .collect::<Vec<_>>()
+            .join(b"\r\n".as_slice());
+        &crlf
+    } else {
+        text
+    };
+
+    let utf16 = |bom: &[u8], to_bytes: fn(u16) -> [u8; 2]| {
+        let mut data = bom.to_vec();
+        for unit in String::from_utf8_lossy(text).encode_utf16() {
+            data.extend_from_slice(&to_bytes(unit));
+        }
+        data
+    };
+    match format.encoding {
+        Encoding::Utf8 => text.to_vec(),
+        Encoding::Utf8Bom => [UTF8_BOM, text].concat(),
+        Encoding::Utf16Le => utf16(UTF16LE_BOM, u16::to_le_bytes),
+        Encoding::Utf16Be => utf16(UTF16BE_BOM, u16::to_be_bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        let mut data = UTF16LE_BOM.to_vec();
+        data.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        data
+    }
+
+    #[test]
+    fn test_decode() {
+        let (format, text) = decode(b"one\r\ntwo\r\n", Decoding::Lines).unwrap();
+        assert_eq!(
+            format,
+            Format {
+                encoding: Encoding::Utf8,
+                crlf: true
+            }
+        );
+        assert_eq!(text, b"one\ntwo\n");
+
+        // Mixed line endings are left alone.
+        let (format, text) = decode(b"one\r\ntwo\n", Decoding::Lines).unwrap();
+        assert!(!format.crlf);
+        assert_eq!(text, b"one\r\ntwo\n");
+
+        let (format, text) = decode(b"\xEF\xBB\xBFcaf\xC3\xA9", Decoding::Lines).unwrap();
+        assert_eq!(format.encoding, Encoding::Utf8Bom);
+        assert_eq!(text, "café".as_bytes());
+
+        let (format, text) = decode(&utf16le("café\r\n"), Decoding::Lines).unwrap();
+        assert_eq!(
+            format,
+            Format {
+                encoding: Encoding::Utf16Le,
+                crlf: true
+            }
+        );
+        assert_eq!(text, "café\n".as_bytes());
+
+        let mut big_endian = UTF16BE_BOM.to_vec();
+        big_endian.extend("é".encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(
+            decode(&big_endian, Decoding::Lines).unwrap().1,
+            "é".as_bytes()
+        );
+
+        assert_eq!(
+            decode(b"\xFF\xFEa", Decoding::Lines),
+            Err(Encoding::Utf16Le)
+        );
+        // An unpaired surrogate.
+        assert_eq!(
+            decode(b"\xFE\xFF\xD8\x00", Decoding::Lines),
+            Err(Encoding::Utf16Be)
+        );
+
+        // Line endings are kept for rules that mention carriage returns.
+        let (format, text) = decode(&utf16le("one\r\n"), Decoding::Text).unwrap();
+        assert_eq!(format.encoding, Encoding::Utf16Le);
+        assert!(!format.crlf);
+        assert_eq!(text, b"one\r\n");
+
+        // And nothing at all is decoded when matching bytes.
+        let (format, text) = decode(b"\xFF\xFEx\r\n", Decoding::Raw).unwrap();
+        assert_eq!(
+            format,
+            Format {
+                encoding: Encoding::Utf8,
+                crlf: false
+            }
+        );
+        assert_eq!(text, b"\xFF\xFEx\r\n");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let files = [
+            b"plain\n".to_vec(),
+            b"crlf\r\nlines\r\n".to_vec(),
+            b"mixed\r\nlines\n\r".to_vec(),
+            b"not utf-8 \xFF\r\n".to_vec(),
+            b"\xEF\xBB\xBFbom\r\n".to_vec(),
+            b"\r\r\n".to_vec(),
+            utf16le("wide\r\ntext ✓\r\n"),
+            b"\xFE\xFF\x00a\x00\n".to_vec(),
+        ];
+        for data in files {
+            for decoding in [Decoding::Raw, Decoding::Text, Decoding::Lines] {
+                let (format, text) = decode(&data, decoding).unwrap();
+                assert_eq!(encode(format, &text), data, "{:?}", data);
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let format = Format {
+            encoding: Encoding::Utf16Le,
+            crlf: true,
+        };
+        assert_eq!(encode(format, "café\n".as_bytes()), utf16le("café\r\n"));
+    }
+}
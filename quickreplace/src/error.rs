@@ -0,0 +1,115 @@
+use crate::encoding::Encoding;
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Everything that can go wrong replacing text.
+///
+/// Each kind of failure has its own exit status, given by `exit_code`:
+///
+/// - 1: bad arguments, such as an invalid glob or rules file
+/// - 2: an input file couldn't be read
+/// - 3: an output file couldn't be written
+/// - 4: the target isn't a valid regular expression
+#[derive(Debug, Error)]
+pub enum QuickReplaceError {
+    #[error("{0}")]
+    BadArgs(String),
+
+    #[error("invalid regular expression: {0}")]
+    Regex(#[from] regex::Error),
+
+    #[error("failed to read from file '{}': {source}", path.display())]
+    Read { path: PathBuf, source: io::Error },
+
+    #[error("failed to read from file '{}': not valid UTF-8", path.display())]
+    NotUtf8 { path: PathBuf },
+
+    #[error("failed to read from file '{}': not valid {encoding}", path.display())]
+    BadEncoding { path: PathBuf, encoding: Encoding },
+
+    #[error("failed to write to file '{}': {source}", path.display())]
+    Write { path: PathBuf, source: io::Error },
+}
+
+impl QuickReplaceError {
+    pub fn read(path: impl Into<PathBuf>, source: io::Error) -> QuickReplaceError {
+        QuickReplaceError::Read {
+            path: path.into(),
+            source,
+        }
+    }
+
+    pub fn write(path: impl Into<PathBuf>, source: io::Error) -> QuickReplaceError {
+        QuickReplaceError::Write {
+            path: path.into(),
+            source,
+        }
+    }
+
+    /// The status quickreplace exits with after this error.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            QuickReplaceError::BadArgs(_) => 1,
+            QuickReplaceError::Read { .. }
+            | QuickReplaceError::NotUtf8 { .. }
+            | QuickReplaceError::BadEncoding { .. } => 2,
+            QuickReplaceError::Write { .. } => 3,
+            QuickReplaceError::Regex(_) => 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regex_error() -> regex::Error {
+        let unclosed = String::from("(");
+        regex::Regex::new(&unclosed).unwrap_err()
+    }
+
+    #[test]
+    fn test_messages() {
+        let not_found = || io::Error::new(io::ErrorKind::NotFound, "no such file");
+        assert_eq!(
+            QuickReplaceError::read("in.txt", not_found()).to_string(),
+            "failed to read from file 'in.txt': no such file"
+        );
+        assert_eq!(
+            QuickReplaceError::write("out.txt", not_found()).to_string(),
+            "failed to write to file 'out.txt': no such file"
+        );
+
+        let error = QuickReplaceError::from(regex_error());
+        let message = error.to_string();
+        assert!(
+            message.starts_with("invalid regular expression:")
+                && message.contains("unclosed group"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let error = || io::Error::other("oops");
+        assert_eq!(QuickReplaceError::BadArgs("bad".into()).exit_code(), 1);
+        assert_eq!(QuickReplaceError::read("a", error()).exit_code(), 2);
+        assert_eq!(
+            QuickReplaceError::NotUtf8 { path: "a".into() }.exit_code(),
+            2
+        );
+        let bad_encoding = QuickReplaceError::BadEncoding {
+            path: "a".into(),
+            encoding: Encoding::Utf16Le,
+        };
+        assert_eq!(
+            bad_encoding.to_string(),
+            "failed to read from file 'a': not valid UTF-16LE"
+        );
+        assert_eq!(bad_encoding.exit_code(), 2);
+        assert_eq!(QuickReplaceError::write("a", error()).exit_code(), 3);
+        assert_eq!(QuickReplaceError::from(regex_error()).exit_code(), 4);
+    }
+}
@@ -0,0 +1,130 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+/// Replace the contents of the file at `path` with `data`, without ever
+/// leaving a partially written file behind.
+///
+/// The new contents are written to a temporary file in the same directory,
+/// given the original file's permissions, flushed to disk, and then renamed
+/// over the original. If `backup_suffix` is given, the original is first
+/// copied to a file with that suffix appended to its name.
+///
+/// If `path` is a symbolic link, the file it points to is replaced and the
+/// link is left alone.
+pub fn write_atomically(path: &Path, data: &[u8], backup_suffix: Option<&str>) -> io::Result<()> {
+    let path = fs::canonicalize(path)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let permissions = fs::metadata(&path)?.permissions();
+
+    // The temporary file must be on the same file system as the original
+    // for the rename to be atomic, hence the same directory.
+    let mut temp = NamedTempFile::new_in(dir)?;
+    temp.write_all(data)?;
+    temp.as_file().set_permissions(permissions)?;
+    temp.as_file().sync_all()?;
+
+    if let Some(suffix) = backup_suffix {
+        fs::copy(&path, backup_path(&path, suffix))?;
+    }
+
+    temp.persist(&path).map_err(|e| e.error)?;
+
+    // Make the rename itself durable.
+    #[cfg(unix)]
+    File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+/// Return the path of the backup of `path`: its file name with `suffix`
+/// appended, like `notes.txt.bak`.
+pub fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "old").unwrap();
+
+        write_atomically(&path, b"new", None).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        // No temporary files are left behind.
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_write_atomically_with_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "old").unwrap();
+
+        write_atomically(&path, b"new", Some(".bak")).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(
+            fs::read_to_string(dir.path().join("notes.txt.bak")).unwrap(),
+            "old"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomically_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.sh");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o751)).unwrap();
+
+        write_atomically(&path, b"new", None).unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o751);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomically_follows_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&target, "old").unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomically(&link, b"new", None).unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_write_atomically_missing_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing.txt");
+        assert!(write_atomically(&path, b"new", None).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_backup_path() {
+        assert_eq!(
+            backup_path(Path::new("src/main.rs"), ".orig"),
+            PathBuf::from("src/main.rs.orig")
+        );
+    }
+}
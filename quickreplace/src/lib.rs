@@ -0,0 +1,35 @@
+//! Change occurrences of one string into another, in text, streams and
+//! files.
+//!
+//! This is the library behind the `quickreplace` command. Build a
+//! [`Replacer`] from a pattern and a replacement, then apply it:
+//!
+//! ```
+//! use quickreplace::Replacer;
+//!
+//! let replacer = Replacer::builder(r"(\w+)@(\w+)", "$2 at $1").build().unwrap();
+//! let replaced = replacer.replace_str("me@home");
+//! assert_eq!(replaced.output, "home at me");
+//! assert_eq!(replaced.edits[0].before, "me@home");
+//! ```
+
+mod case;
+#[doc(hidden)]
+pub mod cli;
+mod context;
+mod encoding;
+mod error;
+mod in_place;
+mod matcher;
+mod replacement;
+mod replacer;
+mod report;
+mod rules;
+mod scope;
+mod stream;
+mod walk;
+
+pub use encoding::Encoding;
+pub use error::QuickReplaceError;
+pub use replacer::{Replaced, Replacer, ReplacerBuilder};
+pub use report::Edit;
@@ -0,0 +1,209 @@
+use crate::replacement::Replacement;
+use crate::report::{Edit, Locator};
+use crate::stream;
+use regex::{Captures, Regex};
+use std::ops::Range;
+use std::str::Utf8Error;
+
+/// A compiled target: a regular expression over text, or, with `--bytes` or
+/// `--stream`, over raw bytes.
+pub enum Matcher {
+    Text(Regex),
+    Bytes(regex::bytes::Regex),
+}
+
+impl Matcher {
+    /// Apply `replace` or `stream::replace_bytes` to `data`, as appropriate.
+    /// Matching text fails if `data` isn't valid UTF-8.
+    pub fn replace(
+        &self,
+        replacement: &Replacement,
+        data: &[u8],
+        limit: usize,
+    ) -> Result<(Vec<u8>, Vec<Edit>), Utf8Error> {
+        match self {
+            Matcher::Text(regex) => {
+                let (replaced, edits) =
+                    replace(regex, replacement, std::str::from_utf8(data)?, limit);
+                Ok((replaced.into_bytes(), edits))
+            }
+            Matcher::Bytes(regex) => Ok(stream::replace_bytes(regex, replacement, data, limit)),
+        }
+    }
+
+    /// Like `replace`, but only replace the matches whose byte range
+    /// `accept` returns true for.
+    pub fn replace_where<F>(
+        &self,
+        replacement: &Replacement,
+        data: &[u8],
+        limit: usize,
+        accept: F,
+    ) -> Result<(Vec<u8>, Vec<Edit>), Utf8Error>
+    where
+        F: Fn(Range<usize>) -> bool,
+    {
+        match self {
+            Matcher::Text(regex) => {
+                let text = std::str::from_utf8(data)?;
+                let (replaced, edits) = replace_with(regex, replacement, text, limit, |caps, _| {
+                    accept(caps.get(0).unwrap().range())
+                });
+                Ok((replaced.into_bytes(), edits))
+            }
+            Matcher::Bytes(regex) => Ok(stream::replace_bytes_with(
+                regex,
+                replacement,
+                data,
+                limit,
+                |caps| accept(caps.get(0).unwrap().range()),
+            )),
+        }
+    }
+}
+
+/// Return a regular expression matching exactly `target`, and a replacement
+/// template that expands to exactly `replacement`.
+pub fn literal(target: &str, replacement: &str) -> (String, String) {
+    (regex::escape(target), replacement.replace('$', "$$"))
+}
+
+/// Replace the first `limit` matches of `regex` in `text`, or all of them if
+/// `limit` is zero, with `replacement`. Return the new text and the
+/// replacements made.
+pub fn replace(
+    regex: &Regex,
+    replacement: &Replacement,
+    text: &str,
+    limit: usize,
+) -> (String, Vec<Edit>) {
+    replace_with(regex, replacement, text, limit, |_, _| true)
+}
+
+/// Like `replace`, but only replace the matches for which `accept`, given
+/// the match and its expanded replacement, returns true. Only accepted
+/// matches count towards `limit`.
+pub fn replace_with<F>(
+    regex: &Regex,
+    replacement: &Replacement,
+    text: &str,
+    limit: usize,
+    mut accept: F,
+) -> (String, Vec<Edit>)
+where
+    F: FnMut(&Captures, &str) -> bool,
+{
+    let mut replaced = String::with_capacity(text.len());
+    let mut edits = vec![];
+    let mut locator = Locator::new(text.as_bytes());
+    let mut last = 0;
+    for caps in regex.captures_iter(text) {
+        if limit != 0 && edits.len() == limit {
+            break;
+        }
+        let found = caps.get(0).unwrap();
+        let mut expanded = String::new();
+        replacement.expand(&caps, edits.len() + 1, &mut expanded);
+        if accept(&caps, &expanded) {
+            edits.push(locator.edit(
+                found.start(),
+                found.as_str().as_bytes(),
+                expanded.as_bytes(),
+            ));
+            replaced.push_str(&text[last..found.start()]);
+            replaced.push_str(&expanded);
+            last = found.end();
+        }
+    }
+    replaced.push_str(&text[last..]);
+    (replaced, edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// `replace`, counting the replacements rather than listing them.
+    fn count(regex: &Regex, replacement: &str, text: &str, limit: usize) -> (String, usize) {
+        let (replaced, edits) = replace(regex, &replacement.parse().unwrap(), text, limit);
+        (replaced, edits.len())
+    }
+
+    #[test]
+    fn test_replace() {
+        let regex = Regex::new("world").unwrap();
+        assert_eq!(
+            count(&regex, "there", "hello world, world", 0),
+            ("hello there, there".to_string(), 2)
+        );
+        assert_eq!(count(&regex, "there", "hello", 0), ("hello".to_string(), 0));
+
+        let regex = Regex::new(r"(?P<key>\w+)=(\w+)").unwrap();
+        assert_eq!(
+            count(&regex, "$2=$key", "a=1 b=2", 0),
+            ("1=a 2=b".to_string(), 2)
+        );
+        assert_eq!(
+            count(&regex, "$2=$key", "a=1 b=2", 1),
+            ("1=a b=2".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn test_replace_literal() {
+        let (target, replacement) = literal("foo(a.b)", "$1 costs $$5");
+        let regex = Regex::new(&target).unwrap();
+        assert_eq!(
+            count(&regex, &replacement, "foo(a.b) foo(axb)", 0),
+            ("$1 costs $$5 foo(axb)".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn test_replace_edits() {
+        let regex = Regex::new("cat").unwrap();
+        let (_, edits) = replace(&regex, &"dog".parse().unwrap(), "a cat\nthe cat", 0);
+        let positions: Vec<(usize, usize)> =
+            edits.iter().map(|edit| (edit.line, edit.column)).collect();
+        assert_eq!(positions, [(1, 3), (2, 5)]);
+        assert_eq!(
+            (edits[0].before.as_str(), edits[0].after.as_str()),
+            ("cat", "dog")
+        );
+    }
+
+    proptest! {
+        /// A literal target that doesn't occur leaves any text as it was,
+        /// whatever the target and replacement hold.
+        #[test]
+        fn prop_replace_literal_unmatched(
+            target in "\\PC{1,8}",
+            replacement in "\\PC{0,8}",
+            text in "\\PC*",
+        ) {
+            prop_assume!(!text.contains(&target));
+            let (target, replacement) = literal(&target, &replacement);
+            let regex = Regex::new(&target).unwrap();
+            let (replaced, edits) = replace(&regex, &replacement.parse().unwrap(), &text, 0);
+            prop_assert_eq!(replaced, text);
+            prop_assert!(edits.is_empty());
+        }
+
+        /// A literal target that does occur is replaced everywhere it does,
+        /// with exactly the replacement given.
+        #[test]
+        fn prop_replace_literal_matched(
+            parts in prop::collection::vec("[a-z ]{0,8}", 1..5),
+            replacement in "\\PC{0,8}",
+        ) {
+            let target = "$1.*";
+            let text = parts.join(target);
+            let (pattern, template) = literal(target, &replacement);
+            let regex = Regex::new(&pattern).unwrap();
+            let (replaced, edits) = replace(&regex, &template.parse().unwrap(), &text, 0);
+            prop_assert_eq!(replaced, parts.join(&replacement));
+            prop_assert_eq!(edits.len(), parts.len() - 1);
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use crate::case::Case;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// What to replace each match with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Replacement {
+    /// A template, parsed from text like `${1:upper}-$name-${n}`.
+    Template(Vec<Piece>),
+    /// Replace each matched text with the text it maps to, for
+    /// `--preserve-case`. Matches missing from the map are replaced with
+    /// `default`.
+    Lookup {
+        map: HashMap<String, String>,
+        default: String,
+    },
+}
+
+/// A part of a template.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Piece {
+    Text(String),
+    /// A value to insert, after passing it through each of `transforms` in
+    /// turn.
+    Value {
+        source: Source,
+        transforms: Vec<Transform>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// A capture group, by number: `$1` or `${1}`.
+    Index(usize),
+    /// A capture group, by name: `$name` or `${name}`.
+    Name(String),
+    /// The number of the match being replaced, counting from 1: `${n}`.
+    /// Unbraced, `$n` refers to a group named `n`, as in the `regex` crate.
+    Counter,
+}
+
+/// A function applied to a value in a template, as in `${1:upper}`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Transform {
+    Upper,
+    Lower,
+    /// Rewrite an identifier in another case, as in `${1:snake}`.
+    Case(Case),
+    /// Add one to a number, or subtract one, keeping any leading zeros.
+    /// Values that aren't numbers are left alone.
+    Inc,
+    Dec,
+}
+
+impl FromStr for Transform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Transform, String> {
+        Ok(match name {
+            "upper" => Transform::Upper,
+            "lower" => Transform::Lower,
+            "snake" => Transform::Case(Case::Snake),
+            "screaming" => Transform::Case(Case::Screaming),
+            "kebab" => Transform::Case(Case::Kebab),
+            "camel" => Transform::Case(Case::Camel),
+            "pascal" => Transform::Case(Case::Pascal),
+            "inc" => Transform::Inc,
+            "dec" => Transform::Dec,
+            _ => return Err(format!("unknown function '{}' in replacement", name)),
+        })
+    }
+}
+
+impl Transform {
+    fn apply(self, value: &str) -> String {
+        match self {
+            Transform::Upper => value.to_uppercase(),
+            Transform::Lower => value.to_lowercase(),
+            Transform::Case(case) => case.convert(value),
+            Transform::Inc => add(value, 1),
+            Transform::Dec => add(value, -1),
+        }
+    }
+}
+
+/// Add `delta` to the integer `value`, padding the result with zeros to the
+/// width `value` had if it had leading zeros.
+fn add(value: &str, delta: i128) -> String {
+    let Ok(number) = value.parse::<i128>() else {
+        return value.to_string();
+    };
+    let Some(sum) = number.checked_add(delta) else {
+        return value.to_string();
+    };
+    let digits = value.trim_start_matches(['-', '+']);
+    if digits.len() > 1 && digits.starts_with('0') {
+        let width = digits.len() + usize::from(sum < 0);
+        format!("{:0width$}", sum, width = width)
+    } else {
+        sum.to_string()
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+impl FromStr for Replacement {
+    type Err = String;
+
+    /// Parse a template. As in the `regex` crate's templates, `$1` and
+    /// `$name` refer to capture groups, `${1}` and `${name}` do the same
+    /// without running into the text after them, `$$` is a literal `$`, and
+    /// a `$` not followed by a name is left alone. Within braces, each
+    /// `:function` after the name transforms the value, and `n` names the
+    /// number of the match rather than a group. Only within braces: `$n`
+    /// still means a group named `n`.
+    fn from_str(template: &str) -> Result<Replacement, String> {
+        let mut pieces = vec![];
+        let mut text = String::new();
+        let mut rest = template;
+        while let Some(dollar) = rest.find('$') {
+            text.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+
+            let (name, transforms, braced) = if let Some(after) = rest.strip_prefix('$') {
+                text.push('$');
+                rest = after;
+                continue;
+            } else if let Some(after) = rest.strip_prefix('{') {
+                let close = after
+                    .find('}')
+                    .ok_or_else(|| format!("unclosed '${{' in replacement '{}'", template))?;
+                let mut parts = after[..close].split(':');
+                let name = parts.next().unwrap_or_default();
+                let transforms = parts.map(str::parse).collect::<Result<Vec<_>, _>>()?;
+                rest = &after[close + 1..];
+                (name, transforms, true)
+            } else {
+                let len = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+                if len == 0 {
+                    text.push('$');
+                    continue;
+                }
+                let name = &rest[..len];
+                rest = &rest[len..];
+                (name, vec![], false)
+            };
+
+            let source = match name {
+                "" => return Err(format!("missing group name in replacement '{}'", template)),
+                "n" if braced => Source::Counter,
+                _ => match name.parse() {
+                    Ok(index) => Source::Index(index),
+                    Err(_) => Source::Name(name.to_string()),
+                },
+            };
+            if !text.is_empty() {
+                pieces.push(Piece::Text(std::mem::take(&mut text)));
+            }
+            pieces.push(Piece::Value { source, transforms });
+        }
+        text.push_str(rest);
+        if !text.is_empty() {
+            pieces.push(Piece::Text(text));
+        }
+        Ok(Replacement::Template(pieces))
+    }
+}
+
+impl Replacement {
+    /// Append the replacement for the match `caps`, the `number`th replaced,
+    /// counting from 1, to `dst`.
+    pub fn expand(&self, caps: &regex::Captures, number: usize, dst: &mut String) {
+        match self {
+            Replacement::Template(pieces) => {
+                for piece in pieces {
+                    match piece {
+                        Piece::Text(text) => dst.push_str(text),
+                        Piece::Value { source, transforms } => {
+                            let value = match source {
+                                Source::Index(i) => caps.get(*i).map_or("", |m| m.as_str()),
+                                Source::Name(name) => caps.name(name).map_or("", |m| m.as_str()),
+                                Source::Counter => {
+                                    dst.push_str(&transform(number.to_string(), transforms));
+                                    continue;
+                                }
+                            };
+                            dst.push_str(&transform(value.to_string(), transforms));
+                        }
+                    }
+                }
+            }
+            Replacement::Lookup { map, default } => {
+                dst.push_str(map.get(&caps[0]).unwrap_or(default));
+            }
+        }
+    }
+
+    /// Like `expand`, for a match of a `regex::bytes::Regex`. Groups are
+    /// inserted as they are, unless transformed, when they're first
+    /// converted to text, with invalid UTF-8 replaced.
+    pub fn expand_bytes(&self, caps: &regex::bytes::Captures, number: usize, dst: &mut Vec<u8>) {
+        match self {
+            Replacement::Template(pieces) => {
+                for piece in pieces {
+                    match piece {
+                        Piece::Text(text) => dst.extend_from_slice(text.as_bytes()),
+                        Piece::Value { source, transforms } => {
+                            let value = match source {
+                                Source::Index(i) => caps.get(*i).map(|m| m.as_bytes()),
+                                Source::Name(name) => caps.name(name).map(|m| m.as_bytes()),
+                                Source::Counter => {
+                                    let value = transform(number.to_string(), transforms);
+                                    dst.extend_from_slice(value.as_bytes());
+                                    continue;
+                                }
+                            };
+                            let value = value.unwrap_or_default();
+                            if transforms.is_empty() {
+                                dst.extend_from_slice(value);
+                            } else {
+                                let value = String::from_utf8_lossy(value).into_owned();
+                                dst.extend_from_slice(transform(value, transforms).as_bytes());
+                            }
+                        }
+                    }
+                }
+            }
+            Replacement::Lookup { map, default } => {
+                let found = String::from_utf8_lossy(&caps[0]);
+                dst.extend_from_slice(map.get(found.as_ref()).unwrap_or(default).as_bytes());
+            }
+        }
+    }
+}
+
+fn transform(value: String, transforms: &[Transform]) -> String {
+    transforms
+        .iter()
+        .fold(value, |value, transform| transform.apply(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(template: &str, pattern: &str, text: &str, number: usize) -> String {
+        let replacement: Replacement = template.parse().unwrap();
+        let caps = regex::Regex::new(pattern).unwrap().captures(text).unwrap();
+        let mut dst = String::new();
+        replacement.expand(&caps, number, &mut dst);
+        dst
+    }
+
+    #[test]
+    fn test_expand() {
+        let pattern = r"(\w+)@(?P<host>\w+)";
+        assert_eq!(expand("$host/$1", pattern, "me@home", 1), "home/me");
+        assert_eq!(
+            expand("${1}x $2x $$1 $", pattern, "me@home", 1),
+            "mex  $1 $"
+        );
+        assert_eq!(expand("$9", pattern, "me@home", 1), "");
+
+        let lookup = Replacement::Lookup {
+            map: HashMap::from([("me@home".to_string(), "found".to_string())]),
+            default: "missing".to_string(),
+        };
+        let regex = regex::Regex::new(pattern).unwrap();
+        let mut dst = String::new();
+        lookup.expand(&regex.captures("me@home").unwrap(), 1, &mut dst);
+        assert_eq!(dst, "found");
+        let mut dst = String::new();
+        lookup.expand(&regex.captures("you@work").unwrap(), 1, &mut dst);
+        assert_eq!(dst, "missing");
+    }
+
+    #[test]
+    fn test_expand_functions() {
+        let pattern = r"(?P<name>\w+) v(\d+)\.(\d+)";
+        let text = "userName v1.09";
+        assert_eq!(
+            expand("${name:snake} v$2.${3:inc}", pattern, text, 1),
+            "user_name v1.10"
+        );
+        assert_eq!(
+            expand("${1:upper} ${1:lower} ${1:kebab:upper}", pattern, text, 1),
+            "USERNAME username USER-NAME"
+        );
+        assert_eq!(
+            expand("${1:pascal}${1:camel}${1:screaming}", pattern, text, 1),
+            "UserNameuserNameUSER_NAME"
+        );
+        assert_eq!(expand("${2:dec} ${1:inc}", pattern, text, 1), "0 userName");
+        assert_eq!(expand("item ${n}, ${n:dec}", pattern, text, 7), "item 7, 6");
+        // Unbraced, $n is a group like any other.
+        assert_eq!(expand("$n ${n}", r"(?P<n>\w+)", "word", 3), "word 3");
+    }
+
+    #[test]
+    fn test_add() {
+        assert_eq!(add("9", 1), "10");
+        assert_eq!(add("099", 1), "100");
+        assert_eq!(add("007", 1), "008");
+        assert_eq!(add("0", -1), "-1");
+        assert_eq!(add("-01", 1), "00");
+        assert_eq!(add("x", 1), "x");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("${1:shout}".parse::<Replacement>().is_err());
+        assert!("${1".parse::<Replacement>().is_err());
+        assert!("${}".parse::<Replacement>().is_err());
+        assert_eq!(
+            "a$$b".parse::<Replacement>(),
+            Ok(Replacement::Template(vec![Piece::Text("a$b".to_string())]))
+        );
+    }
+
+    #[test]
+    fn test_expand_bytes() {
+        let regex = regex::bytes::Regex::new(r"(?-u)(\w+)@(.)").unwrap();
+        let caps = regex.captures(b"me@\xff").unwrap();
+        let mut dst = vec![];
+        let replacement: Replacement = "$2/${1:upper}/${n}".parse().unwrap();
+        replacement.expand_bytes(&caps, 3, &mut dst);
+        assert_eq!(dst, b"\xff/ME/3");
+    }
+}
@@ -0,0 +1,275 @@
+use crate::encoding::Decoding;
+use crate::error::QuickReplaceError;
+use crate::report::Edit;
+use crate::rules::{self, Rule, RuleSpec};
+use crate::{encoding, in_place};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::str::Utf8Error;
+
+/// A compiled pattern and replacement, ready to apply to text, streams and
+/// files. Build one with `Replacer::builder`.
+pub struct Replacer {
+    rule: Rule,
+    limit: usize,
+}
+
+/// Options for a `Replacer`. Each is off unless set, as with the command
+/// line flag of the same name.
+#[derive(Debug, Clone)]
+pub struct ReplacerBuilder {
+    spec: RuleSpec,
+    bytes: bool,
+    limit: usize,
+}
+
+/// The result of replacing in some text: the new text, and each replacement
+/// made, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replaced<T> {
+    pub output: T,
+    pub edits: Vec<Edit>,
+}
+
+impl ReplacerBuilder {
+    /// Match `pattern` literally, rather than as a regular expression, and
+    /// insert the replacement as it is, with no `$` expansion.
+    pub fn literal(mut self, yes: bool) -> ReplacerBuilder {
+        self.spec.fixed_strings = yes;
+        self
+    }
+
+    pub fn ignore_case(mut self, yes: bool) -> ReplacerBuilder {
+        self.spec.ignore_case = yes;
+        self
+    }
+
+    /// Only match whole words.
+    pub fn word(mut self, yes: bool) -> ReplacerBuilder {
+        self.spec.word_regexp = yes;
+        self
+    }
+
+    /// Let `.` match newlines, and `^` and `$` match at the start and end
+    /// of each line.
+    pub fn multiline(mut self, yes: bool) -> ReplacerBuilder {
+        self.spec.multiline = yes;
+        self
+    }
+
+    /// Treat the pattern as an identifier, and replace it in each case it
+    /// appears in with the replacement in the same case.
+    pub fn preserve_case(mut self, yes: bool) -> ReplacerBuilder {
+        self.spec.preserve_case = yes;
+        self
+    }
+
+    /// Match raw bytes, so that input needn't be valid UTF-8.
+    pub fn bytes(mut self, yes: bool) -> ReplacerBuilder {
+        self.bytes = yes;
+        self
+    }
+
+    /// Make at most `limit` replacements in each text, or any number if
+    /// `limit` is zero, the default.
+    pub fn limit(mut self, limit: usize) -> ReplacerBuilder {
+        self.limit = limit;
+        self
+    }
+
+    /// Compile the pattern and replacement.
+    pub fn build(self) -> Result<Replacer, QuickReplaceError> {
+        Ok(Replacer {
+            rule: Rule::new(&self.spec, self.bytes)?,
+            limit: self.limit,
+        })
+    }
+}
+
+impl Replacer {
+    /// Start building a replacer that changes matches of `pattern` into
+    /// `replacement`, a template as on the command line.
+    pub fn builder(pattern: impl Into<String>, replacement: impl Into<String>) -> ReplacerBuilder {
+        ReplacerBuilder {
+            spec: RuleSpec {
+                pattern: pattern.into(),
+                replacement: replacement.into(),
+                ..RuleSpec::default()
+            },
+            bytes: false,
+            limit: 0,
+        }
+    }
+
+    /// How to decode text for this replacer to match against.
+    fn decoding(&self) -> Decoding {
+        rules::decoding(std::slice::from_ref(&self.rule))
+    }
+
+    /// Apply the replacement to `data`, the contents of `path`, if any.
+    fn apply(&self, path: &Path, data: &[u8]) -> Result<(Vec<u8>, Vec<Edit>), Utf8Error> {
+        rules::apply_rules(std::slice::from_ref(&self.rule), path, data, self.limit)
+    }
+
+    /// Replace in `text`. When matching bytes, any invalid UTF-8 the
+    /// replacement leaves is replaced with U+FFFD.
+    pub fn replace_str(&self, text: &str) -> Replaced<String> {
+        let (output, edits) = self
+            .apply(Path::new(""), text.as_bytes())
+            .expect("text is valid UTF-8");
+        let output = match String::from_utf8(output) {
+            Ok(output) => output,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        };
+        Replaced { output, edits }
+    }
+
+    /// Read all of `input`, and write it to `output` with the replacement
+    /// applied. Unless matching bytes, input that isn't valid UTF-8 is an
+    /// `InvalidData` error, and nothing is written.
+    pub fn replace_reader<R: Read, W: Write>(
+        &self,
+        mut input: R,
+        mut output: W,
+    ) -> io::Result<Vec<Edit>> {
+        let mut data = vec![];
+        input.read_to_end(&mut data)?;
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+        let (format, text) = encoding::decode(&data, self.decoding())
+            .map_err(|encoding| invalid(format!("not valid {}", encoding)))?;
+        let (replaced, edits) = self
+            .apply(Path::new(""), &text)
+            .map_err(|e| invalid(e.to_string()))?;
+        output.write_all(&encoding::encode(format, &replaced))?;
+        output.flush()?;
+        Ok(edits)
+    }
+
+    /// Apply the replacement to the file at `path`, rewriting it in place
+    /// if anything changed, without ever leaving it partially written.
+    pub fn replace_path(&self, path: impl AsRef<Path>) -> Result<Vec<Edit>, QuickReplaceError> {
+        let path = path.as_ref();
+        let data = fs::read(path).map_err(|e| QuickReplaceError::read(path, e))?;
+        let (format, text) = encoding::decode_file(path, &data, self.decoding())?;
+        let (replaced, edits) =
+            self.apply(path, &text)
+                .map_err(|_| QuickReplaceError::NotUtf8 {
+                    path: path.to_path_buf(),
+                })?;
+        if replaced != text {
+            let replaced = encoding::encode(format, &replaced);
+            in_place::write_atomically(path, &replaced, None)
+                .map_err(|e| QuickReplaceError::write(path, e))?;
+        }
+        Ok(edits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_str() {
+        let replacer = Replacer::builder("cat", "dog").build().unwrap();
+        let replaced = replacer.replace_str("a cat\nthe cat");
+        assert_eq!(replaced.output, "a dog\nthe dog");
+        let positions: Vec<(usize, usize)> = replaced
+            .edits
+            .iter()
+            .map(|edit| (edit.line, edit.column))
+            .collect();
+        assert_eq!(positions, [(1, 3), (2, 5)]);
+
+        let replacer = Replacer::builder("a.b", "$1")
+            .literal(true)
+            .ignore_case(true)
+            .limit(1)
+            .build()
+            .unwrap();
+        assert_eq!(replacer.replace_str("A.B a.b axb").output, "$1 a.b axb");
+
+        let replacer = Replacer::builder("userName", "accountId")
+            .preserve_case(true)
+            .word(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            replacer.replace_str("user_name USER_NAME userNames").output,
+            "account_id ACCOUNT_ID userNames"
+        );
+
+        assert!(matches!(
+            Replacer::builder("(", "x").build(),
+            Err(QuickReplaceError::Regex(_))
+        ));
+    }
+
+    #[test]
+    fn test_replace_reader() {
+        let replacer = Replacer::builder("^x", "y")
+            .multiline(true)
+            .build()
+            .unwrap();
+        let mut output = vec![];
+        let edits = replacer
+            .replace_reader(b"x\nxx\n".as_slice(), &mut output)
+            .unwrap();
+        assert_eq!(output, b"y\nyx\n");
+        assert_eq!(edits.len(), 2);
+
+        let error = replacer
+            .replace_reader(b"x\xff".as_slice(), &mut vec![])
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let replacer = Replacer::builder("x", "y").bytes(true).build().unwrap();
+        let mut output = vec![];
+        replacer
+            .replace_reader(b"x\xff".as_slice(), &mut output)
+            .unwrap();
+        assert_eq!(output, b"y\xff");
+
+        // Bytes that look like a byte order mark are just bytes.
+        let mut output = vec![];
+        replacer
+            .replace_reader(b"\xFF\xFEx\r\n".as_slice(), &mut output)
+            .unwrap();
+        assert_eq!(output, b"\xFF\xFEy\r\n");
+    }
+
+    #[test]
+    fn test_replace_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "one two").unwrap();
+
+        let replacer = Replacer::builder(r"(\w+) (\w+)", "$2 $1").build().unwrap();
+        let edits = replacer.replace_path(&path).unwrap();
+        assert_eq!(edits[0].after, "two one");
+        assert_eq!(fs::read_to_string(&path).unwrap(), "two one");
+
+        // Files are written back in the encoding and line endings they had.
+        let mut data = b"\xFF\xFE".to_vec();
+        data.extend("one two\r\n".encode_utf16().flat_map(u16::to_le_bytes));
+        fs::write(&path, data).unwrap();
+        let replacer = Replacer::builder("two$", "2")
+            .multiline(true)
+            .build()
+            .unwrap();
+        replacer.replace_path(&path).unwrap();
+        let mut expected = b"\xFF\xFE".to_vec();
+        expected.extend("one 2\r\n".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(fs::read(&path).unwrap(), expected);
+
+        // Unless the target mentions them, when line endings are left alone.
+        fs::write(&path, "one\r\ntwo\r\n").unwrap();
+        let replacer = Replacer::builder(r"\r\n", "\n").build().unwrap();
+        assert_eq!(replacer.replace_path(&path).unwrap().len(), 2);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+
+        let missing = replacer.replace_path(dir.path().join("missing.txt"));
+        assert!(matches!(missing, Err(QuickReplaceError::Read { .. })));
+    }
+}
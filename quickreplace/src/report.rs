@@ -0,0 +1,200 @@
+use serde::Serialize;
+use std::fmt::Write;
+
+/// One replacement made in a file.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Edit {
+    /// The index of the rule that made the replacement.
+    pub rule: usize,
+    /// Where the match starts in the text the rule was applied to, counting
+    /// lines and columns from 1 and columns in characters.
+    pub line: usize,
+    pub column: usize,
+    /// The text matched, and what it was replaced with.
+    pub before: String,
+    pub after: String,
+}
+
+/// Turns successive byte offsets into a text into line and column numbers,
+/// without rescanning the text from the start each time.
+pub struct Locator<'a> {
+    text: &'a [u8],
+    offset: usize,
+    line: usize,
+    line_start: usize,
+}
+
+impl<'a> Locator<'a> {
+    pub fn new(text: &'a [u8]) -> Locator<'a> {
+        Locator {
+            text,
+            offset: 0,
+            line: 1,
+            line_start: 0,
+        }
+    }
+
+    /// Describe replacing `before`, found at `start`, with `after`. Offsets
+    /// must be given in increasing order.
+    pub fn edit(&mut self, start: usize, before: &[u8], after: &[u8]) -> Edit {
+        for (i, &byte) in self.text[self.offset..start].iter().enumerate() {
+            if byte == b'\n' {
+                self.line += 1;
+                self.line_start = self.offset + i + 1;
+            }
+        }
+        self.offset = start;
+
+        let column = String::from_utf8_lossy(&self.text[self.line_start..start])
+            .chars()
+            .count()
+            + 1;
+        Edit {
+            rule: 0,
+            line: self.line,
+            column,
+            before: String::from_utf8_lossy(before).into_owned(),
+            after: String::from_utf8_lossy(after).into_owned(),
+        }
+    }
+}
+
+/// What a run changed, for `--stats` and `--json`.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// The ID under which an `--in-place` run's changes were journaled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<String>,
+    pub files: Vec<FileReport>,
+    pub rules: Vec<RuleReport>,
+    pub files_searched: usize,
+    pub files_changed: usize,
+    pub replacements: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FileReport {
+    pub path: String,
+    pub replacements: usize,
+    pub edits: Vec<Edit>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RuleReport {
+    pub pattern: String,
+    pub replacements: usize,
+}
+
+impl Report {
+    /// Start a report on a run applying rules with the given `patterns`.
+    pub fn new<I: IntoIterator<Item = String>>(patterns: I) -> Report {
+        Report {
+            rules: patterns
+                .into_iter()
+                .map(|pattern| RuleReport {
+                    pattern,
+                    replacements: 0,
+                })
+                .collect(),
+            ..Report::default()
+        }
+    }
+
+    /// Record the `edits` made to the file at `path`.
+    pub fn add_file(&mut self, path: &str, edits: Vec<Edit>) {
+        for edit in &edits {
+            self.rules[edit.rule].replacements += 1;
+        }
+        self.files_changed += 1;
+        self.replacements += edits.len();
+        self.files.push(FileReport {
+            path: path.to_string(),
+            replacements: edits.len(),
+            edits,
+        });
+    }
+
+    /// Describe how many replacements each rule made, one rule per line.
+    pub fn rule_hits(&self) -> String {
+        self.rules
+            .iter()
+            .map(|rule| format!("{:>8}  {}\n", rule.replacements, rule.pattern))
+            .collect()
+    }
+
+    /// List every replacement made, file by file, for people to read.
+    pub fn stats(&self) -> String {
+        let mut out = String::new();
+        for file in &self.files {
+            writeln!(out, "{}: {} replacements", file.path, file.replacements).unwrap();
+            for edit in &file.edits {
+                writeln!(
+                    out,
+                    "  {}:{}: {:?} -> {:?}",
+                    edit.line, edit.column, edit.before, edit.after
+                )
+                .unwrap();
+            }
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locator() {
+        let text = "ab\ncé d\n\nx".as_bytes();
+        let mut locator = Locator::new(text);
+        let positions: Vec<(usize, usize)> = [0, 1, 3, 7, 9, 10]
+            .iter()
+            .map(|&start| {
+                let edit = locator.edit(start, b"", b"");
+                (edit.line, edit.column)
+            })
+            .collect();
+        assert_eq!(positions, [(1, 1), (1, 2), (2, 1), (2, 4), (3, 1), (4, 1)]);
+    }
+
+    fn edit(rule: usize, line: usize, column: usize) -> Edit {
+        Edit {
+            rule,
+            line,
+            column,
+            before: "cat".to_string(),
+            after: "dog".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let mut report = Report::new(["cat".to_string(), "cow".to_string()]);
+        report.files_searched = 3;
+        report.add_file("a.txt", vec![edit(0, 1, 1), edit(0, 2, 5)]);
+        report.add_file("b.txt", vec![edit(1, 3, 1)]);
+
+        assert_eq!(report.files_changed, 2);
+        assert_eq!(report.replacements, 3);
+        assert_eq!(report.rule_hits(), "       2  cat\n       1  cow\n");
+        assert_eq!(
+            report.stats(),
+            "a.txt: 2 replacements\n  \
+             1:1: \"cat\" -> \"dog\"\n  \
+             2:5: \"cat\" -> \"dog\"\n\
+             b.txt: 1 replacements\n  \
+             3:1: \"cat\" -> \"dog\"\n"
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["replacements"], 3);
+        assert_eq!(json["files"][0]["path"], "a.txt");
+        assert_eq!(json["files"][0]["edits"][1]["column"], 5);
+        assert_eq!(json["rules"][1]["replacements"], 1);
+    }
+}
@@ -0,0 +1,589 @@
+use crate::context::{self, Context, LineRange};
+use crate::encoding::Decoding;
+use crate::error::QuickReplaceError;
+use crate::matcher::{literal, Matcher};
+use crate::replacement::Replacement;
+use crate::report::Edit;
+use crate::scope::{Language, Scope, Tokens};
+use crate::{case, walk};
+use regex::RegexBuilder;
+use serde::Deserialize;
+use std::ops::Range;
+use std::path::Path;
+use std::str::Utf8Error;
+
+/// One replacement, as written in a rules file or given on the command line.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuleSpec {
+    pub pattern: String,
+    pub replacement: String,
+    #[serde(default)]
+    pub fixed_strings: bool,
+    #[serde(default)]
+    pub ignore_case: bool,
+    #[serde(default)]
+    pub word_regexp: bool,
+    #[serde(default)]
+    pub multiline: bool,
+    /// Treat `pattern` as a literal identifier, and also replace it in every
+    /// case `case::variants` knows, with `replacement` in the same case.
+    #[serde(default)]
+    pub preserve_case: bool,
+    /// Only replace matches within this kind of token.
+    #[serde(default)]
+    pub scope: Option<Scope>,
+    /// Only replace matches within these lines.
+    #[serde(default)]
+    pub lines: Option<LineRange>,
+    /// Only replace matches with text matching these patterns within
+    /// `within` lines before or after them.
+    #[serde(default)]
+    pub before: Option<String>,
+    #[serde(default)]
+    pub after: Option<String>,
+    #[serde(default)]
+    pub within: Option<usize>,
+    /// Only apply the rule to files matching one of these globs.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Never apply the rule to files matching one of these globs.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// The layout of TOML and YAML rules files: a list of rules under `rule`
+/// (the natural name for a TOML `[[rule]]` table) or `rules`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(alias = "rules")]
+    rule: Vec<RuleSpec>,
+}
+
+/// Parse the contents of the rules file at `path`.
+///
+/// Files named `*.toml` hold `[[rule]]` tables, and files named `*.yaml` or
+/// `*.yml` a `rules` list, each rule having the fields of `RuleSpec`. Any
+/// other file has one `pattern => replacement` rule per line, with no flags
+/// or globs; blank lines and lines starting with `#` are ignored, as is
+/// whitespace around `=>`.
+pub fn parse_rules(path: &Path, text: &str) -> Result<Vec<RuleSpec>, String> {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    match extension {
+        "toml" => toml::from_str::<RulesFile>(text)
+            .map(|file| file.rule)
+            .map_err(|e| e.to_string()),
+        "yaml" | "yml" => serde_yaml::from_str::<RulesFile>(text)
+            .map(|file| file.rule)
+            .map_err(|e| e.to_string()),
+        _ => parse_rule_lines(text),
+    }
+}
+
+fn parse_rule_lines(text: &str) -> Result<Vec<RuleSpec>, String> {
+    let mut rules = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((pattern, replacement)) = line.split_once("=>") else {
+            return Err(format!(
+                "line {}: expected 'pattern => replacement'",
+                number + 1
+            ));
+        };
+        rules.push(RuleSpec {
+            pattern: pattern.trim_end().to_string(),
+            replacement: replacement.trim_start().to_string(),
+            ..RuleSpec::default()
+        });
+    }
+    Ok(rules)
+}
+
+/// A compiled rule, ready to apply.
+pub struct Rule {
+    /// The pattern as written, to report hits against.
+    pub pattern: String,
+    pub matcher: Matcher,
+    pub replacement: Replacement,
+    pub scope: Option<Scope>,
+    pub context: Context,
+    filter: walk::Filter,
+    /// Whether the target or replacement mentions a carriage return.
+    mentions_cr: bool,
+}
+
+impl Rule {
+    /// Compile `spec`, matching raw bytes rather than text if `bytes` is set.
+    pub fn new(spec: &RuleSpec, bytes: bool) -> Result<Rule, QuickReplaceError> {
+        let (target, replacement) = if spec.preserve_case {
+            preserve_case(&spec.pattern, &spec.replacement)
+        } else {
+            let (target, template) = if spec.fixed_strings {
+                literal(&spec.pattern, &spec.replacement)
+            } else {
+                (spec.pattern.clone(), spec.replacement.clone())
+            };
+            let replacement = template.parse().map_err(QuickReplaceError::BadArgs)?;
+            (target, replacement)
+        };
+        let target = if spec.word_regexp {
+            format!(r"\b(?:{})\b", target)
+        } else {
+            target
+        };
+        let mentions_cr = mentions_cr(&target) || spec.replacement.contains('\r');
+
+        let matcher = if bytes {
+            regex::bytes::RegexBuilder::new(&target)
+                .case_insensitive(spec.ignore_case)
+                .multi_line(spec.multiline)
+                .dot_matches_new_line(spec.multiline)
+                .build()
+                .map(Matcher::Bytes)
+        } else {
+            RegexBuilder::new(&target)
+                .case_insensitive(spec.ignore_case)
+                .multi_line(spec.multiline)
+                .dot_matches_new_line(spec.multiline)
+                .build()
+                .map(Matcher::Text)
+        };
+
+        // Anchors are matched against the raw file, whether or not it's
+        // valid UTF-8, with the same flags as the target.
+        let anchor = |pattern: &Option<String>| {
+            pattern
+                .as_ref()
+                .map(|pattern| {
+                    regex::bytes::RegexBuilder::new(pattern)
+                        .case_insensitive(spec.ignore_case)
+                        .multi_line(spec.multiline)
+                        .dot_matches_new_line(spec.multiline)
+                        .build()
+                })
+                .transpose()
+        };
+        let context = Context {
+            lines: spec.lines,
+            before: anchor(&spec.before)?,
+            after: anchor(&spec.after)?,
+            within: spec.within.unwrap_or(context::DEFAULT_WITHIN),
+        };
+
+        let filter = walk::Filter::new(&spec.include, &spec.exclude)
+            .map_err(|e| QuickReplaceError::BadArgs(format!("invalid glob: {}", e)))?;
+        Ok(Rule {
+            pattern: spec.pattern.clone(),
+            matcher: matcher?,
+            replacement,
+            scope: spec.scope,
+            context,
+            filter,
+            mentions_cr,
+        })
+    }
+
+    /// Return true if this rule should be applied to the file at `path`.
+    pub fn applies_to(&self, path: &Path) -> bool {
+        self.filter.accepts(path)
+    }
+
+    /// Return true if this rule only replaces some of its matches, depending
+    /// on where they are.
+    pub fn filters_matches(&self) -> bool {
+        self.scope.is_some() || !self.context.is_empty()
+    }
+
+    /// Return a function telling whether a match at the given byte range of
+    /// `data`, the contents of `path`, is within this rule's scope and
+    /// context.
+    pub fn accepts_match<'a>(
+        &'a self,
+        path: &Path,
+        data: &'a [u8],
+    ) -> impl Fn(Range<usize>) -> bool + 'a {
+        let tokens = self
+            .scope
+            .map(|scope| (scope, Tokens::new(Language::from_path(path), data)));
+        let lines = (!self.context.is_empty()).then(|| context::line_starts(data));
+        move |range| {
+            let in_scope = match &tokens {
+                Some((scope, tokens)) => tokens.contains(*scope, range.clone()),
+                None => true,
+            };
+            in_scope
+                && lines
+                    .as_ref()
+                    .is_none_or(|lines| self.context.accepts(data, lines, range))
+        }
+    }
+}
+
+/// Return true if `target`, a regular expression, matches a carriage return
+/// by name, either as is or escaped as `\r`, `\x0D` or the like.
+fn mentions_cr(target: &str) -> bool {
+    let mut chars = target.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => return true,
+            '\\' => match chars.next() {
+                Some('r') => return true,
+                Some(escape @ ('x' | 'u' | 'U')) => {
+                    let rest = chars.as_str();
+                    let digits = match rest.strip_prefix('{') {
+                        Some(braced) => braced.split('}').next().unwrap_or_default(),
+                        None => {
+                            let width = match escape {
+                                'x' => 2,
+                                'u' => 4,
+                                _ => 8,
+                            };
+                            rest.get(..width).unwrap_or_default()
+                        }
+                    };
+                    if u32::from_str_radix(digits, 16) == Ok(0xD) {
+                        return true;
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    false
+}
+
+/// How to decode files for `rules` to match against. Files are taken as they
+/// are for rules matching bytes. Otherwise CRLF line endings are turned into
+/// LF, so that `$` and `\n` match at the end of each line, unless a rule
+/// mentions carriage returns, and so expects to see them.
+pub fn decoding(rules: &[Rule]) -> Decoding {
+    if rules
+        .iter()
+        .any(|rule| matches!(rule.matcher, Matcher::Bytes(_)))
+    {
+        Decoding::Raw
+    } else if rules.iter().any(|rule| rule.mentions_cr) {
+        Decoding::Text
+    } else {
+        Decoding::Lines
+    }
+}
+
+/// Return a regular expression matching `target` in any of the cases that
+/// `case::variants` knows, and the replacement for each spelling.
+fn preserve_case(target: &str, replacement: &str) -> (String, Replacement) {
+    let mut variants = case::variants(target, replacement);
+    // Try longer spellings first, so that none is cut short by another that's
+    // a prefix of it.
+    variants.sort_by_key(|(spelling, _)| std::cmp::Reverse(spelling.len()));
+    let pattern = variants
+        .iter()
+        .map(|(spelling, _)| regex::escape(spelling))
+        .collect::<Vec<_>>()
+        .join("|");
+    let replacement = Replacement::Lookup {
+        map: variants.into_iter().collect(),
+        default: replacement.to_string(),
+    };
+    (pattern, replacement)
+}
+
+/// Apply each of `rules` that applies to `path`, in order, to `data`, each
+/// rule seeing the output of the ones before it. Each rule makes at most
+/// `limit` replacements, or any number if `limit` is zero.
+///
+/// Return the new data and the replacements made, or an error if a rule
+/// matching text finds that `data` isn't valid UTF-8.
+pub fn apply_rules(
+    rules: &[Rule],
+    path: &Path,
+    data: &[u8],
+    limit: usize,
+) -> Result<(Vec<u8>, Vec<Edit>), Utf8Error> {
+    let mut data = data.to_vec();
+    let mut all_edits = vec![];
+    for (index, rule) in rules.iter().enumerate() {
+        if rule.applies_to(path) {
+            let edits;
+            (data, edits) = if rule.filters_matches() {
+                let accept = rule.accepts_match(path, &data);
+                rule.matcher
+                    .replace_where(&rule.replacement, &data, limit, accept)?
+            } else {
+                rule.matcher.replace(&rule.replacement, &data, limit)?
+            };
+            all_edits.extend(edits.into_iter().map(|edit| Edit {
+                rule: index,
+                ..edit
+            }));
+        }
+    }
+    Ok((data, all_edits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec(pattern: &str, replacement: &str) -> RuleSpec {
+        RuleSpec {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+            ..RuleSpec::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_rules_toml() {
+        let text = r#"
+            [[rule]]
+            pattern = "foo"
+            replacement = "bar"
+
+            [[rule]]
+            pattern = "a.b"
+            replacement = "$0"
+            fixed_strings = true
+            include = ["*.rs"]
+        "#;
+        let rules = parse_rules(Path::new("rules.toml"), text).unwrap();
+        assert_eq!(
+            rules,
+            vec![
+                spec("foo", "bar"),
+                RuleSpec {
+                    fixed_strings: true,
+                    include: vec!["*.rs".to_string()],
+                    ..spec("a.b", "$0")
+                }
+            ]
+        );
+
+        let text = "[[rule]]\npattern = \"x\"\nreplacement = \"y\"\nbogus = 1\n";
+        assert!(parse_rules(Path::new("rules.toml"), text).is_err());
+    }
+
+    #[test]
+    fn test_parse_rules_yaml() {
+        let text = "rules:\n  - pattern: foo\n    replacement: bar\n    ignore_case: true\n";
+        assert_eq!(
+            parse_rules(Path::new("rules.yml"), text).unwrap(),
+            vec![RuleSpec {
+                ignore_case: true,
+                ..spec("foo", "bar")
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_rules_lines() {
+        let text = "# renames\nuser_id => account_id\n\n  (\\d+)px=>${1}rem  \n";
+        assert_eq!(
+            parse_rules(Path::new("rules.txt"), text).unwrap(),
+            vec![spec("user_id", "account_id"), spec(r"(\d+)px", "${1}rem")]
+        );
+
+        let error = parse_rules(Path::new("rules"), "a => b\nnonsense\n").unwrap_err();
+        assert!(error.starts_with("line 2:"), "{}", error);
+    }
+
+    #[test]
+    fn test_apply_rules() {
+        let rules = [
+            Rule::new(&spec("one", "two"), false).unwrap(),
+            Rule::new(&spec("two", "three"), false).unwrap(),
+            Rule::new(
+                &RuleSpec {
+                    exclude: vec!["*.md".to_string()],
+                    ..spec("three", "four")
+                },
+                false,
+            )
+            .unwrap(),
+        ];
+
+        let run = |path: &str| {
+            let (data, edits) = apply_rules(&rules, Path::new(path), b"one two", 0).unwrap();
+            let mut hits = vec![0; rules.len()];
+            for edit in edits {
+                hits[edit.rule] += 1;
+            }
+            (String::from_utf8(data).unwrap(), hits)
+        };
+
+        // Rules apply in order, each to the output of the last.
+        assert_eq!(run("notes.txt"), ("four four".to_string(), vec![1, 2, 2]));
+        assert_eq!(run("notes.md"), ("three three".to_string(), vec![1, 2, 0]));
+    }
+
+    #[test]
+    fn test_rule_preserve_case() {
+        let text = "user_id USER_ID user-id userId UserId user_ids";
+        let expected = "account_id ACCOUNT_ID account-id accountId AccountId account_ids";
+        for bytes in [false, true] {
+            let rule = Rule::new(
+                &RuleSpec {
+                    preserve_case: true,
+                    ..spec("user_id", "account_id")
+                },
+                bytes,
+            )
+            .unwrap();
+            let (replaced, edits) = rule
+                .matcher
+                .replace(&rule.replacement, text.as_bytes(), 0)
+                .unwrap();
+            assert_eq!(String::from_utf8(replaced).unwrap(), expected);
+            assert_eq!(edits.len(), 6);
+        }
+
+        // The target is literal, and word boundaries still apply.
+        let rule = Rule::new(
+            &RuleSpec {
+                preserve_case: true,
+                word_regexp: true,
+                ..spec("a.b", "c.d")
+            },
+            false,
+        )
+        .unwrap();
+        let (replaced, _) = rule
+            .matcher
+            .replace(&rule.replacement, b"a.b axb A.B a.bc", 0)
+            .unwrap();
+        assert_eq!(replaced, b"c.d axb C.D a.bc");
+    }
+
+    #[test]
+    fn test_apply_rules_scope() {
+        let text = b"let user = \"user\"; // user\n";
+        let run = |scope, path: &str| {
+            let rule = Rule::new(
+                &RuleSpec {
+                    scope: Some(scope),
+                    ..spec("user", "member")
+                },
+                false,
+            )
+            .unwrap();
+            let (data, _) = apply_rules(&[rule], Path::new(path), text, 0).unwrap();
+            String::from_utf8(data).unwrap()
+        };
+        assert_eq!(run(Scope::Code, "a.rs"), "let member = \"user\"; // user\n");
+        assert_eq!(
+            run(Scope::Strings, "a.rs"),
+            "let user = \"member\"; // user\n"
+        );
+        assert_eq!(
+            run(Scope::Comments, "a.rs"),
+            "let user = \"user\"; // member\n"
+        );
+        // A file in an unknown language is all code.
+        assert_eq!(
+            run(Scope::Code, "a.txt"),
+            "let member = \"member\"; // member\n"
+        );
+
+        let text = "[[rule]]\npattern = \"a\"\nreplacement = \"b\"\nscope = \"comments\"\n";
+        let specs = parse_rules(Path::new("rules.toml"), text).unwrap();
+        assert_eq!(specs[0].scope, Some(Scope::Comments));
+    }
+
+    #[test]
+    fn test_apply_rules_context() {
+        let text = r#"
+            [[rule]]
+            pattern = "port: \\d+"
+            replacement = "port: 8080"
+            before = "struct Config"
+            within = 1
+
+            [[rule]]
+            pattern = "x"
+            replacement = "y"
+            lines = "4:"
+        "#;
+        let specs = parse_rules(Path::new("rules.toml"), text).unwrap();
+        assert_eq!(
+            specs[1].lines,
+            Some(LineRange {
+                start: 4,
+                end: None
+            })
+        );
+        let rules: Vec<Rule> = specs
+            .iter()
+            .map(|spec| Rule::new(spec, false).unwrap())
+            .collect();
+
+        let data = b"struct Config {\n    port: 80, x\n}\nstruct Other {\n    port: 80, x\n}\n";
+        let (replaced, edits) = apply_rules(&rules, Path::new("a.rs"), data, 0).unwrap();
+        assert_eq!(
+            String::from_utf8(replaced).unwrap(),
+            "struct Config {\n    port: 8080, x\n}\nstruct Other {\n    port: 80, y\n}\n"
+        );
+        assert_eq!(edits.len(), 2);
+
+        let bad = "[[rule]]\npattern = \"a\"\nreplacement = \"b\"\nlines = \"9:1\"\n";
+        assert!(parse_rules(Path::new("rules.toml"), bad).is_err());
+    }
+
+    #[test]
+    fn test_rule_new() {
+        let rule = Rule::new(
+            &RuleSpec {
+                fixed_strings: true,
+                word_regexp: true,
+                ignore_case: true,
+                ..spec("a.b", "$x")
+            },
+            false,
+        )
+        .unwrap();
+        let (replaced, edits) = rule
+            .matcher
+            .replace(&rule.replacement, b"A.B axb a.bc", 0)
+            .unwrap();
+        assert_eq!(replaced, b"$x axb a.bc");
+        assert_eq!(edits.len(), 1);
+
+        assert!(matches!(
+            Rule::new(&spec("(", ""), false),
+            Err(QuickReplaceError::Regex(_))
+        ));
+        assert!(matches!(
+            Rule::new(&spec("a", "${0:shout}"), false),
+            Err(QuickReplaceError::BadArgs(_))
+        ));
+        let bad_glob = RuleSpec {
+            include: vec!["[".to_string()],
+            ..spec("a", "b")
+        };
+        assert!(matches!(
+            Rule::new(&bad_glob, false),
+            Err(QuickReplaceError::BadArgs(_))
+        ));
+    }
+
+    #[test]
+    fn test_decoding() {
+        let decoding_of = |pattern: &str, replacement: &str, bytes: bool| {
+            decoding(&[Rule::new(&spec(pattern, replacement), bytes).unwrap()])
+        };
+        assert_eq!(decoding_of("a$", "b", false), Decoding::Lines);
+        assert_eq!(decoding_of(r"\r\n", r"\n", false), Decoding::Text);
+        assert_eq!(decoding_of(r"[\x0d\n]", "", false), Decoding::Text);
+        assert_eq!(decoding_of(r"\x{D}|\u000D", "", false), Decoding::Text);
+        assert_eq!(decoding_of("a", "b\r", false), Decoding::Text);
+        // An escaped backslash followed by an r isn't a carriage return.
+        assert_eq!(decoding_of(r"\\r", "", false), Decoding::Lines);
+        assert_eq!(decoding_of(r"\x0A", "", false), Decoding::Lines);
+        assert_eq!(decoding_of("a$", "b", true), Decoding::Raw);
+        assert_eq!(decoding(&[]), Decoding::Lines);
+    }
+}
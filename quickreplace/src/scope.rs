@@ -0,0 +1,344 @@
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::ops::Range;
+use std::path::Path;
+
+/// The kinds of token `--scope` can limit replacements to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Anything outside comments and string literals.
+    Code,
+    Comments,
+    /// String and character literals.
+    Strings,
+}
+
+/// The languages whose comments and strings we can find.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    Rust,
+    /// C, C++, Java, JavaScript, Go and the like: `//` and `/* */` comments,
+    /// and strings in `"`, `'` or `` ` ``.
+    CLike,
+    Python,
+    Shell,
+}
+
+impl Language {
+    /// Guess the language of the file at `path` from its extension.
+    pub fn from_path(path: &Path) -> Option<Language> {
+        let extension = path.extension()?.to_str()?;
+        match extension {
+            "rs" => Some(Language::Rust),
+            "c" | "h" | "cc" | "cpp" | "cxx" | "hh" | "hpp" | "cs" | "java" | "kt" | "scala"
+            | "swift" | "go" | "js" | "jsx" | "mjs" | "ts" | "tsx" => Some(Language::CLike),
+            "py" | "pyi" => Some(Language::Python),
+            "sh" | "bash" | "zsh" => Some(Language::Shell),
+            _ => None,
+        }
+    }
+}
+
+/// Where the comments and string literals are in a file.
+pub struct Tokens {
+    /// The comments and strings in order, with the byte range of each.
+    regions: Vec<(Range<usize>, Scope)>,
+}
+
+impl Tokens {
+    /// Find the comments and strings in `text`, written in `language`. If
+    /// the language isn't known, all of `text` is taken to be code.
+    pub fn new(language: Option<Language>, text: &[u8]) -> Tokens {
+        let mut regions = vec![];
+        if let Some(language) = language {
+            let mut i = 0;
+            while i < text.len() {
+                match token_at(language, text, i) {
+                    Some((scope, end)) => {
+                        regions.push((i..end, scope));
+                        i = end;
+                    }
+                    None => i += 1,
+                }
+            }
+        }
+        Tokens { regions }
+    }
+
+    /// Return true if all of `range` lies within tokens of kind `scope`: in a
+    /// single comment or string, or outside all of them for code.
+    pub fn contains(&self, scope: Scope, range: Range<usize>) -> bool {
+        // The first comment or string that ends after the range starts.
+        let index = self
+            .regions
+            .partition_point(|(region, _)| region.end <= range.start);
+        match (scope, self.regions.get(index)) {
+            (Scope::Code, None) => true,
+            (Scope::Code, Some((region, _))) => range.end <= region.start,
+            (_, Some((region, kind))) => {
+                *kind == scope && region.start <= range.start && range.end <= region.end
+            }
+            (_, None) => false,
+        }
+    }
+}
+
+fn is_identifier(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// If a comment or string starts at `text[i]`, return its kind and where it
+/// ends. Unterminated comments and strings run to the end of `text`.
+fn token_at(language: Language, text: &[u8], i: usize) -> Option<(Scope, usize)> {
+    let rest = &text[i..];
+    let after_identifier = i > 0 && is_identifier(text[i - 1]);
+    match language {
+        Language::Rust => {
+            if rest.starts_with(b"//") {
+                Some((Scope::Comments, line_end(text, i)))
+            } else if rest.starts_with(b"/*") {
+                Some((Scope::Comments, block_end(text, i + 2, b"/*", b"*/", true)))
+            } else if after_identifier {
+                None
+            } else if let Some(end) = rust_raw_string(text, i) {
+                Some((Scope::Strings, end))
+            } else if rest.starts_with(b"\"") || rest.starts_with(b"b\"") {
+                let open = if rest[0] == b'b' { i + 2 } else { i + 1 };
+                Some((Scope::Strings, quoted_end(text, open, b"\"", true)))
+            } else if rest.starts_with(b"'") || rest.starts_with(b"b'") {
+                let open = if rest[0] == b'b' { i + 2 } else { i + 1 };
+                rust_char_end(text, open).map(|end| (Scope::Strings, end))
+            } else {
+                None
+            }
+        }
+        Language::CLike => {
+            if rest.starts_with(b"//") {
+                Some((Scope::Comments, line_end(text, i)))
+            } else if rest.starts_with(b"/*") {
+                Some((Scope::Comments, block_end(text, i + 2, b"/*", b"*/", false)))
+            } else if let Some(&quote @ (b'"' | b'\'' | b'`')) = rest.first() {
+                Some((Scope::Strings, quoted_end(text, i + 1, &[quote], true)))
+            } else {
+                None
+            }
+        }
+        Language::Python => {
+            if rest.starts_with(b"#") {
+                Some((Scope::Comments, line_end(text, i)))
+            } else if rest.starts_with(b"\"\"\"") || rest.starts_with(b"'''") {
+                Some((Scope::Strings, quoted_end(text, i + 3, &rest[..3], true)))
+            } else if let Some(&quote @ (b'"' | b'\'')) = rest.first() {
+                Some((Scope::Strings, quoted_end(text, i + 1, &[quote], true)))
+            } else {
+                None
+            }
+        }
+        Language::Shell => {
+            // `#` only starts a comment at the start of a word, so that
+            // `${#array}` and `a#b` are left alone.
+            let word_start = i == 0
+                || matches!(
+                    text[i - 1],
+                    b' ' | b'\t' | b'\n' | b';' | b'|' | b'&' | b'('
+                );
+            if rest.starts_with(b"#") && word_start {
+                Some((Scope::Comments, line_end(text, i)))
+            } else if rest.starts_with(b"'") {
+                Some((Scope::Strings, quoted_end(text, i + 1, b"'", false)))
+            } else if rest.starts_with(b"\"") {
+                Some((Scope::Strings, quoted_end(text, i + 1, b"\"", true)))
+            } else {
+                None
+            }
+        }
+    }
+}
+
+/// The end of the line containing `text[i]`, not counting the newline.
+fn line_end(text: &[u8], i: usize) -> usize {
+    text[i..]
+        .iter()
+        .position(|&byte| byte == b'\n')
+        .map_or(text.len(), |n| i + n)
+}
+
+/// The end of a string whose contents start at `text[i]`, just past the
+/// `close` delimiter. If `escapes` is set, a backslash escapes the byte after
+/// it.
+fn quoted_end(text: &[u8], mut i: usize, close: &[u8], escapes: bool) -> usize {
+    while i < text.len() {
+        if escapes && text[i] == b'\\' {
+            i += 2;
+        } else if text[i..].starts_with(close) {
+            return i + close.len();
+        } else {
+            i += 1;
+        }
+    }
+    text.len()
+}
+
+/// The end of a block comment whose contents start at `text[i]`, just past
+/// the `close` delimiter. If `nested` is set, comments may contain comments.
+fn block_end(text: &[u8], mut i: usize, open: &[u8], close: &[u8], nested: bool) -> usize {
+    let mut depth = 1;
+    while i < text.len() {
+        if text[i..].starts_with(close) {
+            depth -= 1;
+            i += close.len();
+            if depth == 0 {
+                return i;
+            }
+        } else if nested && text[i..].starts_with(open) {
+            depth += 1;
+            i += open.len();
+        } else {
+            i += 1;
+        }
+    }
+    text.len()
+}
+
+/// If a Rust raw string like `r#"..."#` or `br"..."` starts at `text[i]`,
+/// return where it ends. `r#ident`, a raw identifier, isn't one.
+fn rust_raw_string(text: &[u8], i: usize) -> Option<usize> {
+    let mut j = i;
+    if text.get(j) == Some(&b'b') {
+        j += 1;
+    }
+    if text.get(j) != Some(&b'r') {
+        return None;
+    }
+    j += 1;
+    let hashes = text[j..].iter().take_while(|&&byte| byte == b'#').count();
+    j += hashes;
+    if text.get(j) != Some(&b'"') {
+        return None;
+    }
+    let mut close = vec![b'"'];
+    close.extend(std::iter::repeat_n(b'#', hashes));
+    Some(quoted_end(text, j + 1, &close, false))
+}
+
+/// If the `'` before `text[i]` opens a Rust character literal, rather than
+/// naming a lifetime or label, return where the literal ends.
+fn rust_char_end(text: &[u8], i: usize) -> Option<usize> {
+    if text.get(i) == Some(&b'\\') {
+        return Some(quoted_end(text, i, b"'", true));
+    }
+    // One character, of up to four bytes in UTF-8, then the closing quote.
+    let len = match text.get(i)? {
+        byte if *byte < 0x80 => 1,
+        byte if *byte >= 0xf0 => 4,
+        byte if *byte >= 0xe0 => 3,
+        _ => 2,
+    };
+    (text.get(i + len) == Some(&b'\'')).then_some(i + len + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text of each comment and string in `text`, with its kind.
+    fn regions(language: Language, text: &str) -> Vec<(&str, Scope)> {
+        Tokens::new(Some(language), text.as_bytes())
+            .regions
+            .into_iter()
+            .map(|(range, scope)| (&text[range], scope))
+            .collect()
+    }
+
+    #[test]
+    fn test_rust() {
+        use Scope::*;
+        let text = r###"let s = "a \" b"; // note
+/* outer /* inner */ still */ f(r#"raw "q""#, b"x", 'c', '\'', 'é');
+fn g<'a>(x: &'a str) -> r#type { br"\" }"###;
+        assert_eq!(
+            regions(Language::Rust, text),
+            [
+                (r#""a \" b""#, Strings),
+                ("// note", Comments),
+                ("/* outer /* inner */ still */", Comments),
+                (r##"r#"raw "q""#"##, Strings),
+                (r#"b"x""#, Strings),
+                ("'c'", Strings),
+                (r"'\''", Strings),
+                ("'é'", Strings),
+                (r#"br"\""#, Strings),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_c_like() {
+        use Scope::*;
+        let text = "x = 'a' + \"b\\\"c\"; /* d */ y = `e`; // f\n";
+        assert_eq!(
+            regions(Language::CLike, text),
+            [
+                ("'a'", Strings),
+                ("\"b\\\"c\"", Strings),
+                ("/* d */", Comments),
+                ("`e`", Strings),
+                ("// f", Comments),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_python() {
+        use Scope::*;
+        let text = "x = \"\"\"doc \"quoted\"\n\"\"\"  # c\ny = f'{a}' + \"unterminated";
+        assert_eq!(
+            regions(Language::Python, text),
+            [
+                ("\"\"\"doc \"quoted\"\n\"\"\"", Strings),
+                ("# c", Comments),
+                ("'{a}'", Strings),
+                ("\"unterminated", Strings),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shell() {
+        use Scope::*;
+        let text = "echo 'it\\' \"$x\\\"\" ${#a} a#b # done\n#!";
+        assert_eq!(
+            regions(Language::Shell, text),
+            [
+                ("'it\\'", Strings),
+                ("\"$x\\\"\"", Strings),
+                ("# done", Comments),
+                ("#!", Comments),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_contains() {
+        let text = "a(\"b\") // c";
+        let tokens = Tokens::new(Some(Language::CLike), text.as_bytes());
+        let find = |s: &str| {
+            let start = text.find(s).unwrap();
+            start..start + s.len()
+        };
+        assert!(tokens.contains(Scope::Code, find("a(")));
+        assert!(!tokens.contains(Scope::Code, find("b")));
+        assert!(tokens.contains(Scope::Strings, find("b")));
+        assert!(!tokens.contains(Scope::Comments, find("b")));
+        assert!(tokens.contains(Scope::Comments, find("c")));
+        // A match straddling a string and code is in neither.
+        assert!(!tokens.contains(Scope::Code, find("\")")));
+        assert!(!tokens.contains(Scope::Strings, find("\")")));
+
+        // Unknown languages are all code.
+        let tokens = Tokens::new(Language::from_path(Path::new("notes.txt")), b"// x");
+        assert!(tokens.contains(Scope::Code, 3..4));
+        assert!(!tokens.contains(Scope::Comments, 3..4));
+    }
+}
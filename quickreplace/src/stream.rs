@@ -0,0 +1,229 @@
+use crate::replacement::Replacement;
+use crate::report::{Edit, Locator};
+use regex::bytes::{Captures, Regex};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// Replace the first `limit` matches of `regex` in `data`, or all of them if
+/// `limit` is zero, with `replacement`. This is `replace` for arbitrary bytes:
+/// `data` need not be valid UTF-8. Return the new data and the replacements
+/// made.
+pub fn replace_bytes(
+    regex: &Regex,
+    replacement: &Replacement,
+    data: &[u8],
+    limit: usize,
+) -> (Vec<u8>, Vec<Edit>) {
+    replace_bytes_with(regex, replacement, data, limit, |_| true)
+}
+
+/// Like `replace_bytes`, but only replace the matches for which `accept`
+/// returns true. Only accepted matches count towards `limit`.
+pub fn replace_bytes_with<F>(
+    regex: &Regex,
+    replacement: &Replacement,
+    data: &[u8],
+    limit: usize,
+    accept: F,
+) -> (Vec<u8>, Vec<Edit>)
+where
+    F: FnMut(&Captures) -> bool,
+{
+    replace_bytes_after(regex, replacement, data, limit, 0, accept)
+}
+
+/// Like `replace_bytes_with`, numbering the replacements for `${n}` as if
+/// `previous` had been made before them.
+fn replace_bytes_after<F>(
+    regex: &Regex,
+    replacement: &Replacement,
+    data: &[u8],
+    limit: usize,
+    previous: usize,
+    mut accept: F,
+) -> (Vec<u8>, Vec<Edit>)
+where
+    F: FnMut(&Captures) -> bool,
+{
+    let mut replaced = Vec::with_capacity(data.len());
+    let mut edits = vec![];
+    let mut locator = Locator::new(data);
+    let mut last = 0;
+    for caps in regex.captures_iter(data) {
+        if limit != 0 && edits.len() == limit {
+            break;
+        }
+        if !accept(&caps) {
+            continue;
+        }
+        let found = caps.get(0).unwrap();
+        let mut expanded = vec![];
+        replacement.expand_bytes(&caps, previous + edits.len() + 1, &mut expanded);
+        edits.push(locator.edit(found.start(), found.as_bytes(), &expanded));
+        replaced.extend_from_slice(&data[last..found.start()]);
+        replaced.extend_from_slice(&expanded);
+        last = found.end();
+    }
+    replaced.extend_from_slice(&data[last..]);
+    (replaced, edits)
+}
+
+/// Which side of a stream failed.
+#[derive(Debug)]
+pub enum StreamError {
+    Read(io::Error),
+    Write(io::Error),
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamError::Read(e) => write!(f, "read error: {}", e),
+            StreamError::Write(e) => write!(f, "write error: {}", e),
+        }
+    }
+}
+
+/// Copy `input` to `output` one line at a time, replacing matches of `regex`
+/// as `replace_bytes` does, so that only a line needs to be held in memory.
+///
+/// Matches can't span lines. The line terminator, `\n` or `\r\n`, is left out
+/// of the text being matched so that `$` matches at the end of each line. At
+/// most `limit` replacements are made in all, or any number if `limit` is
+/// zero. Return the number of replacements made.
+pub fn replace_lines<R: BufRead, W: Write>(
+    regex: &Regex,
+    replacement: &Replacement,
+    mut input: R,
+    mut output: W,
+    limit: usize,
+) -> Result<usize, StreamError> {
+    let mut total = 0;
+    let mut line = vec![];
+    loop {
+        line.clear();
+        if input
+            .read_until(b'\n', &mut line)
+            .map_err(StreamError::Read)?
+            == 0
+        {
+            break;
+        }
+
+        let (text, terminator) = split_terminator(&line);
+        if limit != 0 && total == limit {
+            output.write_all(&line).map_err(StreamError::Write)?;
+            continue;
+        }
+
+        let remaining = if limit == 0 { 0 } else { limit - total };
+        let (replaced, edits) =
+            replace_bytes_after(regex, replacement, text, remaining, total, |_| true);
+        total += edits.len();
+        output.write_all(&replaced).map_err(StreamError::Write)?;
+        output.write_all(terminator).map_err(StreamError::Write)?;
+    }
+    output.flush().map_err(StreamError::Write)?;
+    Ok(total)
+}
+
+/// Split `line` into its text and its line terminator, if it has one.
+fn split_terminator(line: &[u8]) -> (&[u8], &[u8]) {
+    let len = if line.ends_with(b"\r\n") {
+        2
+    } else if line.ends_with(b"\n") {
+        1
+    } else {
+        0
+    };
+    line.split_at(line.len() - len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_bytes() {
+        let regex = Regex::new("world").unwrap();
+        let (replaced, edits) =
+            replace_bytes(&regex, &"there".parse().unwrap(), b"hello \xffworld\xfe", 0);
+        assert_eq!(replaced, b"hello \xffthere\xfe");
+        assert_eq!((edits[0].line, edits[0].column), (1, 8));
+        assert_eq!(
+            (edits[0].before.as_str(), edits[0].after.as_str()),
+            ("world", "there")
+        );
+
+        // With Unicode off, `.` matches any byte but a newline.
+        let regex = Regex::new(r"(?-u)a.b").unwrap();
+        let (replaced, edits) = replace_bytes(&regex, &"[$0]".parse().unwrap(), b"a\x80b a\nb", 0);
+        assert_eq!(replaced, b"[a\x80b] a\nb");
+        assert_eq!(edits.len(), 1);
+    }
+
+    #[test]
+    fn test_replace_bytes_with() {
+        let regex = Regex::new("a").unwrap();
+        let (replaced, edits) =
+            replace_bytes_with(&regex, &"b".parse().unwrap(), b"aaaa", 2, |caps| {
+                caps.get(0).unwrap().start() != 1
+            });
+        assert_eq!(replaced, b"baba");
+        assert_eq!(edits.len(), 2);
+    }
+
+    #[test]
+    fn test_replace_lines() {
+        let regex = Regex::new(r"(\w+)$").unwrap();
+        let input = b"one two\r\nthree four\nfive".as_slice();
+        let mut output = vec![];
+        assert_eq!(
+            replace_lines(&regex, &"<$1>".parse().unwrap(), input, &mut output, 0).unwrap(),
+            3
+        );
+        assert_eq!(output, b"one <two>\r\nthree <four>\n<five>");
+
+        // Matches are numbered across the whole stream.
+        let regex = Regex::new("x").unwrap();
+        let mut output = vec![];
+        replace_lines(
+            &regex,
+            &"${n}".parse().unwrap(),
+            b"x x\nx\n".as_slice(),
+            &mut output,
+            0,
+        )
+        .unwrap();
+        assert_eq!(output, b"1 2\n3\n");
+    }
+
+    #[test]
+    fn test_replace_lines_limit() {
+        let regex = Regex::new("a").unwrap();
+        let input = b"aa\naa\naa\n".as_slice();
+        let mut output = vec![];
+        assert_eq!(
+            replace_lines(&regex, &"b".parse().unwrap(), input, &mut output, 3).unwrap(),
+            3
+        );
+        assert_eq!(output, b"bb\nba\naa\n");
+    }
+
+    #[test]
+    fn test_replace_lines_errors() {
+        struct Broken;
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let regex = Regex::new("a").unwrap();
+        let result = replace_lines(&regex, &"b".parse().unwrap(), b"a\n".as_slice(), Broken, 0);
+        assert!(matches!(result, Err(StreamError::Write(_))));
+    }
+}
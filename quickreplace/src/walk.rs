@@ -0,0 +1,264 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::{Match, WalkBuilder};
+use std::path::{Path, PathBuf};
+
+/// How many leading bytes of a file we look at to decide if it's binary.
+const BINARY_CHECK_LEN: usize = 8192;
+
+/// Which of the files found while walking should be edited, based on
+/// `--include` and `--exclude` globs.
+pub struct Filter {
+    include: GlobSet,
+    exclude: GlobSet,
+    backup_suffix: Option<String>,
+}
+
+impl Filter {
+    /// Build a filter that accepts paths matching any of the `include` globs
+    /// (or any path at all, if there are none) and none of the `exclude`
+    /// globs.
+    pub fn new(include: &[String], exclude: &[String]) -> Result<Filter, globset::Error> {
+        Ok(Filter {
+            include: build_glob_set(include)?,
+            exclude: build_glob_set(exclude)?,
+            backup_suffix: None,
+        })
+    }
+
+    /// Also skip files whose names end in `suffix`, the backups of files
+    /// edited before, so that we don't edit them, or back them up, in turn.
+    /// An empty suffix names no backups.
+    pub fn skip_backups(mut self, suffix: Option<&str>) -> Filter {
+        self.backup_suffix = suffix
+            .filter(|suffix| !suffix.is_empty())
+            .map(str::to_string);
+        self
+    }
+
+    pub fn accepts(&self, path: &Path) -> bool {
+        (self.include.is_empty() || self.include.is_match(path))
+            && !self.exclude.is_match(path)
+            && !self.is_backup(path)
+    }
+
+    fn is_backup(&self, path: &Path) -> bool {
+        match (&self.backup_suffix, path.file_name()) {
+            (Some(suffix), Some(name)) => name.to_string_lossy().ends_with(suffix.as_str()),
+            _ => false,
+        }
+    }
+}
+
+fn build_glob_set(globs: &[String]) -> Result<GlobSet, globset::Error> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        builder.add(Glob::new(glob)?);
+    }
+    builder.build()
+}
+
+/// Return every file under `paths` that `filter` accepts, in a stable order.
+///
+/// Directories are walked recursively, skipping hidden files and anything
+/// ignored by `.gitignore`, `.ignore` or the global git excludes, as git
+/// itself would. Files named directly in `paths` are always considered, even
+/// if they would be ignored. Paths that can't be read are returned as errors.
+pub fn find_files(paths: &[String], filter: &Filter) -> Vec<Result<PathBuf, ignore::Error>> {
+    let mut found = vec![];
+    for path in paths {
+        let walker = WalkBuilder::new(path)
+            .require_git(false)
+            .sort_by_file_name(|a, b| a.cmp(b))
+            .build();
+
+        for entry in walker {
+            match entry {
+                Ok(entry) => {
+                    let is_file = entry.file_type().is_some_and(|t| t.is_file())
+                        || (entry.depth() == 0 && entry.path().is_file());
+                    if is_file && filter.accepts(entry.path()) {
+                        found.push(Ok(entry.into_path()));
+                    }
+                }
+                Err(e) => found.push(Err(e)),
+            }
+        }
+    }
+    found
+}
+
+/// Return true if `path`, found under `root`, is ignored by a `.gitignore`
+/// or `.ignore` file in `root` or a directory between it and `path`, or by
+/// the global git excludes, so that `find_files` would have skipped it.
+pub fn is_ignored(root: &Path, path: &Path) -> bool {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return false;
+    };
+    // The deepest file with something to say about the path decides, and in
+    // each directory `.ignore` takes precedence over `.gitignore`.
+    for dir in relative.ancestors().skip(1) {
+        let base = root.join(dir);
+        let within = relative.strip_prefix(dir).unwrap_or(relative);
+        for name in [".ignore", ".gitignore"] {
+            let mut builder = GitignoreBuilder::new(&base);
+            if builder.add(base.join(name)).is_some() {
+                continue;
+            }
+            match builder.build() {
+                Ok(ignore) => match ignore.matched_path_or_any_parents(within, false) {
+                    Match::None => {}
+                    found => return found.is_ignore(),
+                },
+                Err(_) => continue,
+            }
+        }
+    }
+    Gitignore::global()
+        .0
+        .matched_path_or_any_parents(relative, false)
+        .is_ignore()
+}
+
+/// Guess whether `data` is the contents of a binary file, the way git does:
+/// by looking for a NUL byte near the start.
+pub fn is_binary(data: &[u8]) -> bool {
+    data[..data.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn relative_paths(root: &Path, found: Vec<Result<PathBuf, ignore::Error>>) -> Vec<String> {
+        found
+            .into_iter()
+            .map(|path| {
+                let path = path.unwrap();
+                let relative = path.strip_prefix(root).unwrap().to_str().unwrap();
+                relative.replace('\\', "/")
+            })
+            .collect()
+    }
+
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::create_dir_all(root.join("src/nested")).unwrap();
+        fs::create_dir_all(root.join("target")).unwrap();
+        fs::write(root.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(root.join("README.md"), "readme").unwrap();
+        fs::write(root.join("debug.log"), "log").unwrap();
+        fs::write(root.join("src/main.rs"), "main").unwrap();
+        fs::write(root.join("src/nested/lib.rs"), "lib").unwrap();
+        fs::write(root.join("target/out.rs"), "out").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_find_files_respects_gitignore() {
+        let dir = tree();
+        let root = dir.path();
+        let filter = Filter::new(&[], &[]).unwrap();
+
+        let found = find_files(&[root.to_str().unwrap().to_string()], &filter);
+        assert_eq!(
+            relative_paths(root, found),
+            vec!["README.md", "src/main.rs", "src/nested/lib.rs"]
+        );
+    }
+
+    #[test]
+    fn test_find_files_with_globs() {
+        let dir = tree();
+        let root = dir.path();
+        let paths = [root.to_str().unwrap().to_string()];
+
+        let filter = Filter::new(&["*.rs".to_string()], &[]).unwrap();
+        assert_eq!(
+            relative_paths(root, find_files(&paths, &filter)),
+            vec!["src/main.rs", "src/nested/lib.rs"]
+        );
+
+        let filter = Filter::new(&["*.rs".to_string()], &["**/nested/**".to_string()]).unwrap();
+        assert_eq!(
+            relative_paths(root, find_files(&paths, &filter)),
+            vec!["src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn test_find_files_named_directly() {
+        let dir = tree();
+        let root = dir.path();
+        let filter = Filter::new(&[], &[]).unwrap();
+
+        // Named explicitly, an ignored file is still edited.
+        let paths = [root.join("debug.log").to_str().unwrap().to_string()];
+        assert_eq!(
+            relative_paths(root, find_files(&paths, &filter)),
+            vec!["debug.log"]
+        );
+
+        let paths = [root.join("missing.txt").to_str().unwrap().to_string()];
+        let found = find_files(&paths, &filter);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].is_err());
+    }
+
+    #[test]
+    fn test_find_files_skips_backups() {
+        let dir = tree();
+        let root = dir.path();
+        fs::write(root.join("src/main.rs.bak"), "main").unwrap();
+        let paths = [root.to_str().unwrap().to_string()];
+
+        let filter = Filter::new(&[], &[]).unwrap().skip_backups(Some(".bak"));
+        assert_eq!(
+            relative_paths(root, find_files(&paths, &filter)),
+            vec!["README.md", "src/main.rs", "src/nested/lib.rs"]
+        );
+
+        for suffix in [None, Some("")] {
+            let filter = Filter::new(&[], &[]).unwrap().skip_backups(suffix);
+            assert_eq!(find_files(&paths, &filter).len(), 4);
+        }
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        let dir = tree();
+        let root = dir.path();
+        fs::write(root.join("src/.gitignore"), "*.gen\n!keep.log\n").unwrap();
+        for (path, ignored) in [
+            ("README.md", false),
+            ("debug.log", true),
+            ("target/out.rs", true),
+            ("target/deeper/out.rs", true),
+            ("src/main.rs", false),
+            ("src/nested/code.gen", true),
+            // A deeper file can take back what a shallower one ignores.
+            ("src/keep.log", false),
+        ] {
+            assert_eq!(is_ignored(root, &root.join(path)), ignored, "{}", path);
+        }
+        assert!(!is_ignored(root, Path::new("/elsewhere/debug.log")));
+    }
+
+    #[test]
+    fn test_filter_bad_glob() {
+        assert!(Filter::new(&["[".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(!is_binary(b""));
+        assert!(!is_binary(b"plain text\n"));
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+
+        let mut late_nul = vec![b'a'; BINARY_CHECK_LEN];
+        late_nul.push(0);
+        assert!(!is_binary(&late_nul));
+    }
+}
@@ -0,0 +1,198 @@
+//! End-to-end tests of the `quickreplace` binary.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+/// Run quickreplace in `dir` with `args`.
+fn quickreplace(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_quickreplace"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_input_to_output() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("in.txt"), "Hello, world\n").unwrap();
+
+    let output = quickreplace(dir.path(), &["world", "there", "in.txt", "out.txt"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("out.txt")).unwrap(),
+        "Hello, there\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("in.txt")).unwrap(),
+        "Hello, world\n"
+    );
+}
+
+#[test]
+fn test_stdin_to_stdout() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_quickreplace"))
+        .args([r"(\w+)@(\w+)", "$2 at $1", "-", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(b"me@home\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(output.stdout, b"home at me\n");
+}
+
+#[test]
+fn test_in_place_and_undo() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    fs::write(dir.path().join("src/a.rs"), "let cat = 1;\n").unwrap();
+    fs::write(dir.path().join("src/b.txt"), "cat\n").unwrap();
+
+    let output = quickreplace(
+        dir.path(),
+        &["--in-place", "--include", "*.rs", "cat", "dog", "src"],
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 replacements in 1 of 1 files"));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/a.rs")).unwrap(),
+        "let dog = 1;\n"
+    );
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/b.txt")).unwrap(),
+        "cat\n"
+    );
+
+    let output = quickreplace(dir.path(), &["--undo"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(
+        fs::read_to_string(dir.path().join("src/a.rs")).unwrap(),
+        "let cat = 1;\n"
+    );
+}
+
+#[test]
+fn test_dry_run() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a.txt"), "old\n").unwrap();
+
+    let output = quickreplace(dir.path(), &["--dry-run", "old", "new", "."]);
+    assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+    let diff = String::from_utf8_lossy(&output.stdout);
+    assert!(diff.contains("-old") && diff.contains("+new"), "{}", diff);
+    assert_eq!(
+        fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+        "old\n"
+    );
+
+    let output = quickreplace(dir.path(), &["--dry-run", "missing", "new", "."]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+}
+
+#[test]
+fn test_exit_bad_arguments() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("in.txt"), "text").unwrap();
+
+    let output = quickreplace(dir.path(), &["only-a-target"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("error:"), "{}", stderr(&output));
+
+    let output = quickreplace(dir.path(), &["a", "b", "in.txt", "out.txt", "extra.txt"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("expected an input and an output path, got 3 paths"));
+
+    let output = quickreplace(dir.path(), &["--in-place", "--include", "[", "a", "b", "."]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains("invalid glob"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn test_exit_read_error() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = quickreplace(dir.path(), &["a", "b", "missing.txt", "out.txt"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stderr(&output).contains("failed to read from file 'missing.txt'"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!dir.path().join("out.txt").exists());
+}
+
+#[test]
+fn test_exit_write_error() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("in.txt"), "a").unwrap();
+
+    let output = quickreplace(dir.path(), &["a", "b", "in.txt", "no/such/dir/out.txt"]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(
+        stderr(&output).contains("failed to write to file 'no/such/dir/out.txt'"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn test_exit_bad_regex() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("in.txt"), "a").unwrap();
+
+    let output = quickreplace(dir.path(), &["(unclosed", "b", "in.txt", "out.txt"]);
+    assert_eq!(output.status.code(), Some(4));
+    assert!(
+        stderr(&output).contains("invalid regular expression"),
+        "{}",
+        stderr(&output)
+    );
+
+    // The same target is fine taken literally.
+    let output = quickreplace(
+        dir.path(),
+        &["--fixed-strings", "(unclosed", "b", "in.txt", "out.txt"],
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+}
+
+#[test]
+fn test_crlf() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("crlf.txt"), "one\r\ntwo\r\n").unwrap();
+
+    // Line endings are kept as they were.
+    let output = quickreplace(
+        dir.path(),
+        &["--multiline", "two$", "2", "crlf.txt", "out.txt"],
+    );
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(
+        fs::read(dir.path().join("out.txt")).unwrap(),
+        b"one\r\n2\r\n"
+    );
+
+    // Unless the target or replacement is about them.
+    let output = quickreplace(dir.path(), &[r"\r\n", "\n", "crlf.txt", "out.txt"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(fs::read(dir.path().join("out.txt")).unwrap(), b"one\ntwo\n");
+
+    let output = quickreplace(dir.path(), &["two", "2\r", "crlf.txt", "out.txt"]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert_eq!(
+        fs::read(dir.path().join("out.txt")).unwrap(),
+        b"one\r\n2\r\r\n"
+    );
+}